@@ -9,25 +9,37 @@
 //!         "&AElig": { "codepoints": [198], "characters": "\u00C6" },
 //!         . . .
 //!     }
+//!
+//! A crate using htmlize can replace entities.json by setting the environment
+//! variable `HTMLIZE_ENTITIES_JSON` to the path of another file in the same
+//! format, or add entities by setting `HTMLIZE_EXTRA_ENTITIES_JSON` to a list
+//! of paths separated like `PATH`.
 
 fn main() {
     #[cfg(any(feature = "unescape_fast", feature = "entities"))]
-    let entities = load_entities("entities.json");
+    let entities = load_all_entities();
 
     #[cfg(feature = "unescape_fast")]
     generate_matcher_rs(&entities);
 
     #[cfg(feature = "entities")]
     generate_entities_rs(&entities);
+
+    #[cfg(feature = "_unescape_either")]
+    generate_folded_rs(&entities);
 }
 
 /// Generate entities.rs file containing all valid HTML entities in a
-/// [`phf::Map`] along with a few useful constants. It also generates
-/// documentation with all entities in a table.
+/// [`phf::Map`] along with a few useful constants, a reverse map from
+/// expansions to entity names, a sorted array of `Entity` structs, and the
+/// `entity!` macro. It also generates documentation with all entities in a
+/// table.
 #[cfg(feature = "entities")]
 fn generate_entities_rs(entities: &[(String, String)]) {
     use std::cmp::{max, min};
+    use std::collections::BTreeMap;
     use std::env;
+    use std::fmt::Write as _;
     use std::fs::File;
     use std::io::{BufWriter, Write};
     use std::path::Path;
@@ -84,13 +96,71 @@ fn generate_entities_rs(entities: &[(String, String)]) {
         writeln!(out, "/// {name:30} | {codepoints:18} | {glyph}").unwrap();
     }
 
+    // Group names by expansion. Prefer names with semicolons, then shorter
+    // names, then lowercase names, e.g. `&amp;` rather than `&AMP;`.
+    let mut names_by_glyph: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (name, glyph) in entities {
+        names_by_glyph
+            .entry(glyph.as_str())
+            .or_default()
+            .push(name.as_str());
+    }
+
+    let mut names_builder = phf_codegen::Map::<&str>::new();
+    for (glyph, names) in &mut names_by_glyph {
+        names.sort_by_key(|name| preference(name));
+        names_builder.entry(glyph, format!("&{names:?}"));
+    }
+
+    // Entity metadata sorted by name, with the semicolon form first.
+    let mut all: Vec<(&str, bool, &str)> = entities
+        .iter()
+        .map(|(name, glyph)| {
+            let bare = name.trim_start_matches('&');
+            let trimmed = bare.trim_end_matches(';');
+            (trimmed, trimmed.len() == bare.len(), glyph.as_str())
+        })
+        .collect();
+    all.sort_unstable();
+
+    let mut all_entities = String::new();
+    for (name, legacy, glyph) in &all {
+        let codepoints = glyph
+            .chars()
+            .map(|c| u32::from(c).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(
+            all_entities,
+            "    Entity {{ name: {name:?}, legacy: {legacy}, \
+            codepoints: &[{codepoints}], glyph: {glyph:?} }},"
+        )
+        .unwrap();
+    }
+
     let map = map_builder.build();
+    let names = names_builder.build();
+    let entity_macro = entity_macro(&all);
     writeln!(
         out,
         "\
         #[allow(clippy::unreadable_literal)]\n\
         pub static ENTITIES: phf::Map<&[u8], &[u8]> = {map};\n\
         \n\
+        /// A map of expansions to the names of all entities that expand to them,\n\
+        /// e.g. `\"©\"` to `[\"&copy;\", \"&COPY;\", \"&copy\", \"&COPY\"]`. The\n\
+        /// preferred name comes first.\n\
+        #[allow(clippy::unreadable_literal)]\n\
+        #[allow(\n\
+            clippy::redundant_pub_crate,\n\
+            reason = \"`pub` would export it with `pub use entities::*`\"\n\
+        )]\n\
+        pub(crate) static ENTITY_NAMES: phf::Map<&str, &[&str]> = {names};\n\
+        \n\
+        /// All entities sorted by name. See [`all_entities()`].\n\
+        #[allow(clippy::unreadable_literal)]\n\
+        static ALL_ENTITIES: &[Entity] = &[\n{all_entities}];\n\
+        \n\
         /// Length of longest entity including ‘&’ and possibly ‘;’.\n\
         pub const ENTITY_MAX_LENGTH: usize = {max_len};\n\
         \n\
@@ -98,16 +168,77 @@ fn generate_entities_rs(entities: &[(String, String)]) {
         pub const ENTITY_MIN_LENGTH: usize = {min_len};\n\
         \n\
         /// Length of longest semicolon-less entity including ‘&’.\n\
-        pub const BARE_ENTITY_MAX_LENGTH: usize = {bare_max_len};"
+        pub const BARE_ENTITY_MAX_LENGTH: usize = {bare_max_len};\n\
+        \n\
+        {entity_macro}"
     )
     .unwrap();
 }
 
+/// Generate the `entity!` macro, which has one arm for each entity with a
+/// semicolon. `all` contains `(name, legacy, glyph)` for every entity.
+#[cfg(feature = "entities")]
+fn entity_macro(all: &[(&str, bool, &str)]) -> String {
+    use std::fmt::Write;
+
+    let mut entity_arms = String::new();
+    for (name, legacy, glyph) in all {
+        if !legacy {
+            writeln!(entity_arms, "    ({name:?}) => {{ {glyph:?} }};")
+                .unwrap();
+        }
+    }
+
+    format!(
+        "\
+        /// Get the glyph for a named entity at compile time.\n\
+        ///\n\
+        /// The name does not include `&` or `;`, e.g. `\"times\"` for `&times;`.\n\
+        /// This expands to a `&'static str` literal, so it can be used in constants\n\
+        /// and with [`concat!`]. See [`lookup_entity()`] to look up names at runtime.\n\
+        ///\n\
+        /// ```rust\n\
+        /// use htmlize::entity;\n\
+        /// # use assert2::assert;\n\
+        ///\n\
+        /// const TIMES: &str = entity!(\"times\");\n\
+        /// assert!(TIMES == \"×\");\n\
+        /// assert!(concat!(\"3\", entity!(\"nbsp\"), \"kg\") == \"3\\u{{a0}}kg\");\n\
+        /// ```\n\
+        ///\n\
+        /// An unknown name is a compile error. Names are case-sensitive:\n\
+        ///\n\
+        /// ```rust,compile_fail\n\
+        /// let times = htmlize::entity!(\"Times\");\n\
+        /// ```\n\
+        #[macro_export]\n\
+        #[cfg_attr(docsrs, doc(cfg(feature = \"entities\")))]\n\
+        macro_rules! entity {{\n\
+        {entity_arms}\
+            ($name:literal) => {{\n\
+                compile_error!(concat!(\"unknown entity: \", $name))\n\
+            }};\n\
+        }}"
+    )
+}
+
+/// Sort key for ordering entities with the same expansion. Lower is better;
+/// see [`generate_entities_rs()`].
+#[cfg(feature = "entities")]
+fn preference(name: &str) -> (bool, usize, std::cmp::Reverse<&str>) {
+    (!name.ends_with(';'), name.len(), std::cmp::Reverse(name))
+}
+
 /// Generated matcher.rs file containing a function `entity_matcher()` that is
 /// basically just a giant nested tree of `match` expressions to check if the
-/// next bytes in an iterator are an HTML entity.
+/// next bytes in an iterator are an HTML entity, and `matcher_len.rs`
+/// containing the constant `MATCHGEN_MAX_LENGTH`.
 #[cfg(feature = "unescape_fast")]
 fn generate_matcher_rs(entities: &[(String, String)]) {
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+
     let mut matcher = matchgen::TreeMatcher::new(
         "fn entity_matcher",
         "(bool, &'static [u8])",
@@ -124,22 +255,172 @@ fn generate_matcher_rs(entities: &[(String, String)]) {
         .input_type(matchgen::Input::Slice)
         .write_to_out_dir("matcher.rs")
         .unwrap();
+
+    let max_len = entities.iter().map(|(name, _)| name.len()).max().unwrap();
+    let out_path =
+        Path::new(&env::var("OUT_DIR").unwrap()).join("matcher_len.rs");
+    fs::write(
+        out_path,
+        format!(
+            "/// Length of longest entity including ‘&’ and possibly ‘;’.\n\
+            const MATCHGEN_MAX_LENGTH: usize = {max_len};\n"
+        ),
+    )
+    .unwrap();
 }
 
-/// Load HTML entities as `vec![...("&gt;", ">")...]`.
+/// Generate folded.rs file containing `FOLDED_ENTITIES`, a sorted array of
+/// entities by ASCII lowercase name for case-insensitive matching, and the
+/// constant `FOLDED_MAX_LENGTH`.
+#[cfg(feature = "_unescape_either")]
+fn generate_folded_rs(entities: &[(String, String)]) {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::fmt::Write as _;
+    use std::fs;
+    use std::path::Path;
+
+    // `entities` is sorted, so uppercase names come first. Prefer the name
+    // that is already lowercase, e.g. `&nbsp;` rather than `&NBSP;`.
+    let mut folded: BTreeMap<String, &str> = BTreeMap::new();
+    for (name, glyph) in entities {
+        let key = name.to_ascii_lowercase();
+        if key == *name || !folded.contains_key(&key) {
+            folded.insert(key, glyph);
+        }
+    }
+
+    let mut items = String::new();
+    for (name, glyph) in &folded {
+        writeln!(
+            items,
+            "    (&{:?}, &{:?}),",
+            name.as_bytes(),
+            glyph.as_bytes()
+        )
+        .unwrap();
+    }
+
+    let max_len = folded.keys().map(String::len).max().unwrap_or(0);
+    let out_path = Path::new(&env::var("OUT_DIR").unwrap()).join("folded.rs");
+    fs::write(
+        out_path,
+        format!(
+            "\
+            /// Entities by ASCII lowercase name, e.g. `b\"&nbsp;\"`, sorted by name.\n\
+            ///\n\
+            /// If more than one entity has the same lowercase name, the one that is\n\
+            /// already lowercase is used, or else the first one in ASCII order.\n\
+            #[allow(clippy::unreadable_literal)]\n\
+            static FOLDED_ENTITIES: &[(&[u8], &[u8])] = &[\n{items}];\n\
+            \n\
+            /// Length of longest entity in `FOLDED_ENTITIES` including ‘&’ and possibly\n\
+            /// ‘;’.\n\
+            const FOLDED_MAX_LENGTH: usize = {max_len};\n"
+        ),
+    )
+    .unwrap();
+}
+
+/// Load entities from entities.json or `HTMLIZE_ENTITIES_JSON`, and then from
+/// any files in `HTMLIZE_EXTRA_ENTITIES_JSON`.
+///
+/// Entities in later files replace entities with the same name in earlier
+/// files. Returns `vec![...("&gt;", ">")...]` sorted by name.
 #[cfg(any(feature = "unescape_fast", feature = "entities"))]
-fn load_entities<P: AsRef<std::path::Path>>(path: P) -> Vec<(String, String)> {
-    let input = std::fs::read(path.as_ref()).unwrap();
+fn load_all_entities() -> Vec<(String, String)> {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::path::PathBuf;
+
+    println!("cargo:rerun-if-env-changed=HTMLIZE_ENTITIES_JSON");
+    println!("cargo:rerun-if-env-changed=HTMLIZE_EXTRA_ENTITIES_JSON");
+
+    let mut paths = vec![env::var_os("HTMLIZE_ENTITIES_JSON")
+        .map_or_else(|| PathBuf::from("entities.json"), PathBuf::from)];
+    if let Some(extra) = env::var_os("HTMLIZE_EXTRA_ENTITIES_JSON") {
+        paths.extend(env::split_paths(&extra));
+    }
+
+    let mut entities = BTreeMap::new();
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+        entities.extend(load_entities(&path));
+    }
+
+    check_entities(&entities);
+    entities.into_iter().collect()
+}
+
+/// Load entities from a file as `vec![...("&gt;", ">")...]`.
+///
+/// Uses `"characters"` if it’s present, and `"codepoints"` otherwise.
+#[cfg(any(feature = "unescape_fast", feature = "entities"))]
+fn load_entities(path: &std::path::Path) -> Vec<(String, String)> {
+    let input = std::fs::read(path).unwrap_or_else(|error| {
+        panic!("could not read entities from {}: {error}", path.display())
+    });
     let input: serde_json::Map<String, serde_json::Value> =
-        serde_json::from_slice(&input).unwrap();
+        serde_json::from_slice(&input).unwrap_or_else(|error| {
+            panic!("invalid entities JSON in {}: {error}", path.display())
+        });
 
     input
         .iter()
         .map(|(name, info)| {
-            (
-                name.clone(),
-                info["characters"].as_str().unwrap().to_owned(),
-            )
+            let expansion = info["characters"]
+                .as_str()
+                .map(str::to_owned)
+                .or_else(|| {
+                    info["codepoints"]
+                        .as_array()?
+                        .iter()
+                        .map(|codepoint| {
+                            char::from_u32(
+                                u32::try_from(codepoint.as_u64()?).ok()?,
+                            )
+                        })
+                        .collect()
+                })
+                .unwrap_or_else(|| {
+                    panic!(
+                        "entity {name:?} in {} needs valid \"characters\" or \
+                        \"codepoints\"",
+                        path.display()
+                    )
+                });
+            (name.clone(), expansion)
         })
         .collect()
 }
+
+/// Check that entity names will work with the generated code.
+///
+/// Names must be `&` followed by ASCII letters and digits, optionally ending
+/// with `;`. Every name without a `;` must also have a version with one, since
+/// the matchers assume that the longest matching name ends with `;`.
+#[cfg(any(feature = "unescape_fast", feature = "entities"))]
+fn check_entities(entities: &std::collections::BTreeMap<String, String>) {
+    assert!(!entities.is_empty(), "no entities were loaded");
+
+    for name in entities.keys() {
+        let valid = name
+            .strip_prefix('&')
+            .map(|body| body.strip_suffix(';').unwrap_or(body))
+            .map_or(false, |body| {
+                !body.is_empty()
+                    && body.bytes().all(|c| c.is_ascii_alphanumeric())
+            });
+        assert!(
+            valid,
+            "invalid entity name {name:?}: must be '&' followed by ASCII \
+            letters and digits, optionally ending with ';'"
+        );
+
+        assert!(
+            name.ends_with(';') || entities.contains_key(&format!("{name};")),
+            "entity {name:?} has no semicolon, so {name:?} + \";\" must also \
+            be defined"
+        );
+    }
+}
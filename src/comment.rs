@@ -0,0 +1,180 @@
+//! # Escape text for use inside an HTML comment
+
+use std::borrow::Cow;
+
+/// Sequences that may not appear anywhere in the text of a comment.
+const FORBIDDEN: [&[u8]; 3] = [b"<!--", b"-->", b"--!>"];
+
+/// Escape a string to be used inside an HTML comment, i.e. between `<!--` and
+/// `-->`.
+///
+/// Entities are not expanded inside comments, so this does not touch `&` or
+/// `<`. Instead, it inserts a space to break up anything that would end the
+/// comment early or make it invalid [according to the spec][spec]:
+///
+///   * `>` or `->` at the start.
+///   * `<!--`, `-->`, or `--!>` anywhere.
+///   * `<!-` at the end.
+///
+/// ```rust
+/// use htmlize::escape_comment;
+/// # use assert2::assert;
+///
+/// assert!(escape_comment("a -- b & <c>") == "a -- b & <c>");
+/// assert!(escape_comment("--> <script>") == "-- > <script>");
+/// assert!(escape_comment("->x <!-- y --!> <!-") == "- >x <!- - y --! > <!- ");
+/// ```
+///
+/// To work with bytes (`[u8]`) instead of strings, see
+/// [`escape_comment_bytes()`].
+///
+/// # Panics
+///
+/// Panics if the escaped bytes are invalid UTF-8. This should be impossible.
+///
+/// [spec]: https://html.spec.whatwg.org/multipage/syntax.html#comments
+pub fn escape_comment<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    let input = input.into();
+
+    match escape_comment_internal(input.as_bytes()) {
+        Some(output) => String::from_utf8(output).unwrap().into(),
+        None => input,
+    }
+}
+
+/// Escape a byte string to be used inside an HTML comment, i.e. between `<!--`
+/// and `-->`.
+///
+/// See [`escape_comment()`] for details.
+///
+/// ```rust
+/// use htmlize::escape_comment_bytes;
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_comment_bytes(b"\xFF-->".as_slice()) == b"\xFF-- >".as_slice()
+/// );
+/// ```
+///
+/// To work with `String` instead of bytes, see [`escape_comment()`].
+pub fn escape_comment_bytes<'a, S: Into<Cow<'a, [u8]>>>(
+    input: S,
+) -> Cow<'a, [u8]> {
+    let input = input.into();
+
+    match escape_comment_internal(&input) {
+        Some(output) => output.into(),
+        None => input,
+    }
+}
+
+/// Insert spaces into `raw` so that it is valid comment text, or return `None`
+/// if it already is.
+///
+/// A space is inserted before any byte that would complete a forbidden
+/// sequence. Since none of the sequences contain a space, that’s always enough
+/// to break it, and it can’t create a new one.
+fn escape_comment_internal(raw: &[u8]) -> Option<Vec<u8>> {
+    // Indices of bytes that need a space inserted before them.
+    let mut breaks = Vec::new();
+    // Nothing before the last break can be part of a forbidden sequence.
+    let mut start = 0;
+
+    for i in 0..raw.len() {
+        let tail = &raw[start.max(i.saturating_sub(3))..=i];
+        let forbidden = FORBIDDEN.iter().any(|&seq| tail.ends_with(seq))
+            || (start == 0 && matches!(&raw[..=i], b">" | b"->"));
+        if forbidden {
+            breaks.push(i);
+            start = i;
+        }
+    }
+
+    let tail = &raw[start.max(raw.len().saturating_sub(3))..];
+    let trailing = tail == b"<!-";
+
+    if breaks.is_empty() && !trailing {
+        return None;
+    }
+
+    #[allow(
+        clippy::arithmetic_side_effects,
+        reason = "there can’t be more breaks than bytes"
+    )]
+    let mut output = Vec::with_capacity(raw.len() + breaks.len() + 1);
+    let mut written = 0;
+    for i in breaks {
+        output.extend_from_slice(&raw[written..i]);
+        output.push(b' ');
+        written = i;
+    }
+    output.extend_from_slice(&raw[written..]);
+    if trailing {
+        output.push(b' ');
+    }
+
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    macro_rules! test {
+        ($name:ident, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                #![allow(clippy::string_lit_as_bytes)]
+                assert!(escape_comment($input) == $expected);
+                assert!(
+                    escape_comment_bytes($input.as_bytes())
+                        == $expected.as_bytes()
+                );
+            }
+        };
+    }
+
+    test!(empty, "", "");
+    test!(
+        clean,
+        "a - b -- c > d & <e> <!- f",
+        "a - b -- c > d & <e> <!- f"
+    );
+    test!(leading_gt, ">a", " >a");
+    test!(leading_dash_gt, "->a", "- >a");
+    test!(only_gt, ">", " >");
+    test!(only_dash_gt, "->", "- >");
+    test!(not_leading, "a>->", "a>->");
+    test!(open, "<!--", "<!- -");
+    test!(close, "-->", "-- >");
+    test!(bang_close, "--!>", "--! >");
+    test!(trailing_open, "a<!-", "a<!- ");
+    test!(only_trailing_open, "<!-", "<!- ");
+    test!(trailing_open_broken, "<!--<!-", "<!- -<!- ");
+    test!(open_close_overlap, "<!-->", "<!- ->");
+    test!(long_dashes, "<!------>", "<!- ----- >");
+    test!(close_after_break, "<!--->", "<!- -- >");
+    test!(repeated, "-->-->", "-- >-- >");
+    test!(unicode, "→ -->é", "→ -- >é");
+
+    #[test]
+    fn clean_is_borrowed() {
+        assert!(matches!(escape_comment("a -- b"), Cow::Borrowed(_)));
+    }
+
+    /// Check that escaped text is valid according to the spec.
+    #[test]
+    fn output_is_valid() {
+        let inputs = ["<!--->", "<!-<!--->", "->-->", "--!--!>", "<<!--!-->"];
+        for input in inputs {
+            let output = escape_comment(input);
+            assert!(!output.starts_with('>'), "{input:?}");
+            assert!(!output.starts_with("->"), "{input:?}");
+            assert!(!output.ends_with("<!-"), "{input:?}");
+            for seq in ["<!--", "-->", "--!>"] {
+                assert!(!output.contains(seq), "{input:?}");
+            }
+        }
+    }
+}
@@ -3,3 +3,396 @@
 //! Everything here is automatically generated by build.rs.
 
 include!(concat!(env!("OUT_DIR"), "/entities.rs"));
+
+/// Information about a named entity.
+///
+/// See [`all_entities()`] and [`lookup_entity()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Entity {
+    /// The name without `&` or `;`, e.g. `"copy"` for `&copy;`.
+    pub name: &'static str,
+    /// Whether this is the legacy form without a semicolon, e.g. `&copy`.
+    /// Legacy entities always have a form with a semicolon, too.
+    pub legacy: bool,
+    /// The code points the entity expands to, e.g. `[169]` for `&copy;`.
+    pub codepoints: &'static [u32],
+    /// The text the entity expands to, e.g. `"©"` for `&copy;`.
+    pub glyph: &'static str,
+}
+
+/// Get information about all named entities, sorted by name.
+///
+/// If an entity has a legacy form without a semicolon, it comes right after
+/// the form with a semicolon.
+///
+/// ```rust
+/// use htmlize::all_entities;
+/// # use assert2::assert;
+///
+/// let names: Vec<_> = all_entities()
+///     .iter()
+///     .filter(|entity| entity.name.starts_with("copy"))
+///     .map(|entity| (entity.name, entity.legacy))
+///     .collect();
+/// assert!(names == [("copy", false), ("copy", true), ("copysr", false)]);
+/// ```
+#[must_use]
+pub const fn all_entities() -> &'static [Entity] {
+    ALL_ENTITIES
+}
+
+/// Look up a named entity by its name without `&` or `;`, e.g. `"copy"`.
+///
+/// This returns the form with a semicolon. Names are case-sensitive.
+///
+/// ```rust
+/// use htmlize::lookup_entity;
+/// # use assert2::assert;
+///
+/// let entity = lookup_entity("copy").unwrap();
+/// assert!(entity.glyph == "©");
+/// assert!(entity.codepoints == [0xA9]);
+/// assert!(!entity.legacy);
+///
+/// assert!(lookup_entity("Copy") == None);
+/// assert!(lookup_entity("&copy;") == None);
+/// ```
+#[must_use]
+pub fn lookup_entity(name: &str) -> Option<&'static Entity> {
+    ALL_ENTITIES
+        .binary_search_by(|entity| (entity.name, entity.legacy).cmp(&(name, false)))
+        .ok()
+        .map(|i| &ALL_ENTITIES[i])
+}
+
+/// Get all named entities whose names start with `prefix`, sorted by name.
+///
+/// The prefix does not include `&`. Names are case-sensitive. As with
+/// [`all_entities()`], legacy forms without a semicolon come right after the
+/// form with a semicolon. This is useful for autocompletion:
+///
+/// ```rust
+/// use htmlize::entities_with_prefix;
+/// # use assert2::assert;
+///
+/// let names: Vec<_> = entities_with_prefix("tim")
+///     .iter()
+///     .filter(|entity| !entity.legacy)
+///     .map(|entity| entity.name)
+///     .collect();
+/// assert!(names == ["times", "timesb", "timesbar", "timesd"]);
+///
+/// assert!(entities_with_prefix("").len() == htmlize::all_entities().len());
+/// assert!(entities_with_prefix("Tim").is_empty());
+/// ```
+#[must_use]
+pub fn entities_with_prefix(prefix: &str) -> &'static [Entity] {
+    let start = ALL_ENTITIES.partition_point(|entity| entity.name < prefix);
+    let rest = &ALL_ENTITIES[start..];
+    &rest[..rest.partition_point(|entity| entity.name.starts_with(prefix))]
+}
+
+/// Get the names of all entities that expand to a character.
+///
+/// The names include `&` and, if they have one, `;`. The preferred name comes
+/// first: the one that [`escape_text_named()`](crate::escape_text_named) uses.
+/// Returns an empty slice if no entity expands to `c`.
+///
+/// ```rust
+/// use htmlize::entity_names_for_char;
+/// # use assert2::assert;
+///
+/// assert!(entity_names_for_char('×') == ["&times;", "&times"]);
+/// assert!(entity_names_for_char('&') == ["&amp;", "&AMP;", "&amp", "&AMP"]);
+/// assert!(entity_names_for_char('a').is_empty());
+/// ```
+///
+/// To find entities that expand to more than one character, see
+/// [`entity_names_for_str()`].
+#[must_use]
+pub fn entity_names_for_char(c: char) -> &'static [&'static str] {
+    entity_names_for_str(c.encode_utf8(&mut [0; 4]))
+}
+
+/// Get the names of all entities that expand to exactly `s`.
+///
+/// This works like [`entity_names_for_char()`], but also finds entities that
+/// expand to more than one character.
+///
+/// ```rust
+/// use htmlize::entity_names_for_str;
+/// # use assert2::assert;
+///
+/// assert!(entity_names_for_str("≫\u{20D2}") == ["&nGt;"]);
+/// assert!(entity_names_for_str("fj") == ["&fjlig;"]);
+/// assert!(entity_names_for_str("×").len() == 2);
+/// assert!(entity_names_for_str("××").is_empty());
+/// ```
+#[must_use]
+pub fn entity_names_for_str(s: &str) -> &'static [&'static str] {
+    ENTITY_NAMES.get(s).copied().unwrap_or_default()
+}
+
+/// Suggest named entities for a reference that might be misspelled.
+///
+/// `reference` may include `&` and `;`, e.g. `"&nbps;"` or `"nbps"`. Returns
+/// the names of the closest entities, including `&` and `;`, with the best
+/// match first. Returns an empty `Vec` if no entity is close.
+///
+/// Entities are ranked by edit distance ignoring case, then by edit distance
+/// with case. Swapping two adjacent characters counts as one edit. Longer
+/// names may be further away: names with up to 2 characters must match
+/// exactly (ignoring case), names with up to 5 characters may be 1 edit away,
+/// and longer names may be 2 edits away.
+///
+/// ```rust
+/// use htmlize::suggest_entities;
+/// # use assert2::assert;
+///
+/// assert!(suggest_entities("&nbps;")[0] == "&nbsp;");
+/// assert!(suggest_entities("&Copy;")[..2] == ["&copy;", "&COPY;"]);
+/// assert!(suggest_entities("&hellip")[0] == "&hellip;");
+/// assert!(suggest_entities("&nonsense;").is_empty());
+/// ```
+///
+/// To get suggestions for a diagnostic from `unescape_with_diagnostics()`, use
+/// `Diagnostic::suggestions()` (requires feature `unescape` or
+/// `unescape_fast`).
+#[must_use]
+pub fn suggest_entities(reference: &str) -> Vec<&'static str> {
+    let name = reference.strip_prefix('&').unwrap_or(reference);
+    let name = name.strip_suffix(';').unwrap_or(name);
+    let exact: Vec<char> = name.chars().collect();
+    let folded: Vec<char> = exact.iter().map(char::to_ascii_lowercase).collect();
+    let max_distance = match exact.len() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    };
+
+    let mut suggestions: Vec<_> = ENTITY_NAMES
+        .values()
+        .flat_map(|names| names.iter())
+        .filter_map(|&full| {
+            let candidate: Vec<char> =
+                full.strip_prefix('&')?.strip_suffix(';')?.chars().collect();
+            if candidate.len().abs_diff(exact.len()) > max_distance {
+                return None;
+            }
+            let candidate_folded: Vec<char> =
+                candidate.iter().map(char::to_ascii_lowercase).collect();
+            let distance = edit_distance(&folded, &candidate_folded);
+            (distance <= max_distance)
+                .then(|| (distance, edit_distance(&exact, &candidate), full))
+        })
+        .collect();
+    suggestions.sort_unstable();
+    suggestions.into_iter().map(|(_, _, full)| full).collect()
+}
+
+/// Get the optimal string alignment distance between `a` and `b`.
+///
+/// This is the Levenshtein distance, except that swapping two adjacent
+/// characters counts as one edit.
+#[allow(
+    clippy::arithmetic_side_effects,
+    reason = "distances and indices are at most a.len() + b.len()"
+)]
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, &x) in a.iter().enumerate() {
+        let mut current = Vec::with_capacity(previous.len());
+        current.push(i + 1);
+        for (j, &y) in b.iter().enumerate() {
+            let mut distance = (previous[j] + usize::from(x != y))
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+            if i > 0 && j > 0 && x == b[j - 1] && a[i - 1] == y {
+                distance = distance.min(before[j - 1] + 1);
+            }
+            current.push(distance);
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    #[test]
+    fn all_entities_matches_map() {
+        assert!(all_entities().len() == ENTITIES.len());
+        for entity in all_entities() {
+            let semicolon = if entity.legacy { "" } else { ";" };
+            let key = format!("&{}{semicolon}", entity.name);
+            assert!(
+                ENTITIES.get(key.as_bytes()) == Some(&entity.glyph.as_bytes()),
+                "{key}"
+            );
+            assert!(
+                entity.glyph.chars().map(u32::from).eq(entity
+                    .codepoints
+                    .iter()
+                    .copied()),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn all_entities_sorted() {
+        assert!(all_entities()
+            .windows(2)
+            .all(|pair| (pair[0].name, pair[0].legacy)
+                < (pair[1].name, pair[1].legacy)));
+    }
+
+    #[test]
+    fn lookup_every_entity() {
+        for entity in all_entities() {
+            let found = lookup_entity(entity.name).unwrap();
+            assert!(found.name == entity.name);
+            assert!(!found.legacy);
+            assert!(found.glyph == entity.glyph);
+        }
+    }
+
+    #[test]
+    fn lookup_multiple_codepoints() {
+        let entity = lookup_entity("nGt").unwrap();
+        assert!(entity.codepoints == [0x226B, 0x20D2]);
+        assert!(entity.glyph == "\u{226B}\u{20D2}");
+    }
+
+    #[test]
+    fn prefix_matches_filter() {
+        for prefix in ["", "a", "am", "amp", "ampx", "N", "not", "zz", "~"] {
+            let expected: Vec<_> = all_entities()
+                .iter()
+                .filter(|entity| entity.name.starts_with(prefix))
+                .collect();
+            assert!(
+                entities_with_prefix(prefix).iter().eq(expected),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn prefix_whole_name() {
+        let found = entities_with_prefix("amp");
+        assert!(found.iter().all(|entity| entity.name == "amp"));
+        assert!(found.len() == 2);
+        assert!(entities_with_prefix("&amp").is_empty());
+    }
+
+    #[test]
+    fn names_for_every_entity() {
+        for (name, glyph) in &ENTITIES {
+            let name = std::str::from_utf8(name).unwrap();
+            let glyph = std::str::from_utf8(glyph).unwrap();
+            assert!(entity_names_for_str(glyph).contains(&name), "{name}");
+        }
+
+        let total: usize = ENTITY_NAMES.values().map(|names| names.len()).sum();
+        assert!(total == ENTITIES.len());
+    }
+
+    #[test]
+    fn names_preferred_first() {
+        assert!(entity_names_for_char('<') == ["&lt;", "&LT;", "&lt", "&LT"]);
+        assert!(entity_names_for_char('\u{A0}') == ["&nbsp;", "&NonBreakingSpace;", "&nbsp"]);
+        assert!(entity_names_for_char('≫')[0] == "&gg;");
+        assert!(entity_names_for_char('\n') == ["&NewLine;"]);
+    }
+
+    #[test]
+    fn names_missing() {
+        assert!(entity_names_for_str("").is_empty());
+        assert!(entity_names_for_char('\u{20D2}').is_empty());
+        assert!(entity_names_for_str("&amp;").is_empty());
+    }
+
+    /// Get the edit distance between two strings.
+    fn distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        edit_distance(&a, &b)
+    }
+
+    #[test]
+    fn edit_distances() {
+        assert!(distance("", "") == 0);
+        assert!(distance("", "abc") == 3);
+        assert!(distance("abc", "") == 3);
+        assert!(distance("nbsp", "nbsp") == 0);
+        assert!(distance("nbps", "nbsp") == 1);
+        assert!(distance("nbp", "nbsp") == 1);
+        assert!(distance("nbssp", "nbsp") == 1);
+        assert!(distance("nbxp", "nbsp") == 1);
+        assert!(distance("kitten", "sitting") == 3);
+        assert!(distance("ca", "abc") == 3);
+        assert!(distance("Copy", "copy") == 1);
+    }
+
+    #[test]
+    fn suggest_typos() {
+        assert!(suggest_entities("&nbps;")[0] == "&nbsp;");
+        assert!(suggest_entities("nbps")[0] == "&nbsp;");
+        assert!(suggest_entities("&ampp;")[0] == "&amp;");
+        assert!(suggest_entities("&helip;").contains(&"&hellip;"));
+        assert!(suggest_entities("&rarrr;")[0] == "&rarr;");
+    }
+
+    #[test]
+    fn suggest_case() {
+        let suggestions = suggest_entities("&Copy;");
+        assert!(suggestions[..2] == ["&copy;", "&COPY;"]);
+        assert!(suggest_entities("&ALPHA;")[..2] == ["&Alpha;", "&alpha;"]);
+        assert!(suggest_entities("&GT") == ["&GT;", "&Gt;", "&gt;"]);
+    }
+
+    #[test]
+    fn suggest_missing_semicolon() {
+        assert!(suggest_entities("&hellip")[0] == "&hellip;");
+        assert!(suggest_entities("&amp")[0] == "&amp;");
+    }
+
+    #[test]
+    fn suggest_nothing() {
+        assert!(suggest_entities("").is_empty());
+        assert!(suggest_entities("&").is_empty());
+        assert!(suggest_entities("&;").is_empty());
+        assert!(suggest_entities("&xx;").is_empty());
+        assert!(suggest_entities("&#123;").is_empty());
+        assert!(suggest_entities("&nonsense;").is_empty());
+    }
+
+    #[test]
+    fn suggest_only_semicolon_forms() {
+        for name in suggest_entities("&amp") {
+            assert!(name.starts_with('&') && name.ends_with(';'), "{name}");
+        }
+    }
+
+    #[test]
+    fn entity_macro() {
+        const MULTIPLE: &str = entity!("nGt");
+        assert!(entity!("amp") == "&");
+        assert!(entity!("AMP") == "&");
+        assert!(entity!("NewLine") == "\n");
+        assert!(MULTIPLE == lookup_entity("nGt").unwrap().glyph);
+        assert!(entity!("zwnj") == "\u{200C}");
+    }
+
+    #[test]
+    fn lookup_missing() {
+        assert!(lookup_entity("") == None);
+        assert!(lookup_entity("nope") == None);
+        assert!(lookup_entity("amp;") == None);
+    }
+}
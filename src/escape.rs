@@ -2,6 +2,7 @@
 
 use pastey::paste;
 use std::borrow::Cow;
+use std::io;
 
 /// Find a `u8` in a slice. You may specify as many bytes to search for as you
 /// want. If you are searching for 3 or fewer bytes, this will use [`memchr`].
@@ -20,7 +21,68 @@ macro_rules! find_u8_body {
     };
 }
 
-/// Generate string and byte string versions of an escape function.
+/// Generate the functions that find and escape special bytes for an escape
+/// function: `$name_find_u8()`, `$name_map_u8()`, and `$name_bytes_internal()`.
+macro_rules! escape_table {
+    (
+        $name:ident {
+            $($ch:literal => $entity:literal,)+
+        }
+    ) => {
+        paste! {
+            /// Find the first byte in `haystack` that needs to be escaped.
+            #[inline]
+            fn [<$name _find_u8>](haystack: &[u8]) -> Option<usize> {
+                find_u8_body!(haystack, $($ch),+)
+            }
+
+            /// Get the entity for a byte found by `find_u8()`.
+            #[inline]
+            const fn [<$name _map_u8>](c: u8) -> &'static [u8] {
+                match c {
+                    $( $ch => $entity, )+
+                    // This should never happen, but using unreachable!()
+                    // actually makes other parts of the function slower.
+                    _ => b"",
+                }
+            }
+
+            #[inline(always)]
+            fn [<$name _bytes_internal>](raw: &[u8]) -> Option<Vec<u8>> {
+                if let Some(i) = [<$name _find_u8>](raw) {
+                    let mut output: Vec<u8> = Vec::with_capacity(raw.len().saturating_mul(2));
+                    output.extend_from_slice(&raw[..i]);
+                    output.extend_from_slice([<$name _map_u8>](raw[i]));
+
+                    // i is a valid index, so it can't be usize::MAX.
+                    debug_assert!(i < usize::MAX);
+                    #[allow(clippy::arithmetic_side_effects)]
+                    let mut remainder = &raw[i+1..];
+
+                    while let Some(i) = [<$name _find_u8>](remainder) {
+                        output.extend_from_slice(&remainder[..i]);
+                        output.extend_from_slice([<$name _map_u8>](remainder[i]));
+
+                        // i is a valid index, so it can't be usize::MAX.
+                        debug_assert!(i < usize::MAX);
+                        #[allow(clippy::arithmetic_side_effects)]
+                        let n = i + 1; // Work around https://github.com/rust-lang/rust/issues/15701
+                        remainder = &remainder[n..];
+                    }
+
+                    output.extend_from_slice(&remainder);
+
+                    Some(output)
+                } else {
+                    None
+                }
+            }
+        }
+    };
+}
+
+/// Generate string and byte string versions of an escape function, as well as
+/// an [`io::Write`] adapter that escapes everything written through it.
 macro_rules! escape_fn {
     (
         $(#[$meta:meta])*
@@ -29,6 +91,9 @@ macro_rules! escape_fn {
         $(#[$bytes_meta:meta])*
         $bytes_vis:vis fn $bytes_name:ident;
 
+        $(#[$writer_meta:meta])*
+        $writer_vis:vis struct $writer_name:ident;
+
         {
             $($ch:literal => $entity:literal,)+
         }
@@ -54,52 +119,78 @@ macro_rules! escape_fn {
                 }
             }
 
-            #[inline(always)]
-            fn [<$name _bytes_internal>](raw: &[u8]) -> Option<Vec<u8>> {
-                #[inline]
-                fn find_u8(haystack: &[u8]) -> Option<usize> {
-                    find_u8_body!(haystack, $($ch),+)
+            $(#[$writer_meta])*
+            #[derive(Debug)]
+            $writer_vis struct $writer_name<W: io::Write> {
+                /// The writer that receives escaped output.
+                inner: W,
+            }
+
+            impl<W: io::Write> $writer_name<W> {
+                /// Wrap `inner` so that everything written is escaped first.
+                pub const fn new(inner: W) -> Self {
+                    Self { inner }
                 }
 
-                #[inline]
-                const fn map_u8(c: u8) -> &'static [u8] {
-                    match c {
-                        $( $ch => $entity, )+
-                        // This should never happen, but using unreachable!()
-                        // actually makes other parts of the function slower.
-                        _ => b"",
-                    }
+                /// Get a reference to the underlying writer.
+                pub const fn get_ref(&self) -> &W {
+                    &self.inner
                 }
 
-                if let Some(i) = find_u8(raw) {
-                    let mut output: Vec<u8> = Vec::with_capacity(raw.len().saturating_mul(2));
-                    output.extend_from_slice(&raw[..i]);
-                    output.extend_from_slice(map_u8(raw[i]));
+                /// Get a mutable reference to the underlying writer.
+                ///
+                /// Anything written directly to the underlying writer will not
+                /// be escaped.
+                pub fn get_mut(&mut self) -> &mut W {
+                    &mut self.inner
+                }
 
-                    // i is a valid index, so it can't be usize::MAX.
-                    debug_assert!(i < usize::MAX);
-                    #[allow(clippy::arithmetic_side_effects)]
-                    let mut remainder = &raw[i+1..];
+                /// Unwrap this adapter, returning the underlying writer.
+                pub fn into_inner(self) -> W {
+                    self.inner
+                }
+            }
 
-                    while let Some(i) = find_u8(remainder) {
-                        output.extend_from_slice(&remainder[..i]);
-                        output.extend_from_slice(map_u8(remainder[i]));
+            impl<W: io::Write> io::Write for $writer_name<W> {
+                fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                    match [<$name _find_u8>](buf) {
+                        Some(0) => {
+                            // Entities are always written completely, so that
+                            // we never have to keep track of a partial entity.
+                            self.inner.write_all([<$name _map_u8>](buf[0]))?;
+                            Ok(1)
+                        }
+                        Some(i) => self.inner.write(&buf[..i]),
+                        None => self.inner.write(buf),
+                    }
+                }
+
+                fn write_all(&mut self, mut buf: &[u8]) -> io::Result<()> {
+                    while let Some(i) = [<$name _find_u8>](buf) {
+                        self.inner.write_all(&buf[..i])?;
+                        self.inner.write_all([<$name _map_u8>](buf[i]))?;
 
                         // i is a valid index, so it can't be usize::MAX.
                         debug_assert!(i < usize::MAX);
                         #[allow(clippy::arithmetic_side_effects)]
                         let n = i + 1; // Work around https://github.com/rust-lang/rust/issues/15701
-                        remainder = &remainder[n..];
+                        buf = &buf[n..];
                     }
 
-                    output.extend_from_slice(&remainder);
+                    self.inner.write_all(buf)
+                }
 
-                    Some(output)
-                } else {
-                    None
+                fn flush(&mut self) -> io::Result<()> {
+                    self.inner.flush()
                 }
             }
         }
+
+        escape_table! {
+            $name {
+                $($ch => $entity,)+
+            }
+        }
     }
 }
 
@@ -138,6 +229,26 @@ escape_fn! {
     /// To work with `String` instead of bytes, see [`escape_text()`].
     pub fn escape_text_bytes;
 
+    /// Escape everything written to an [`io::Write`] as text, i.e. as if it
+    /// was passed to [`escape_text_bytes()`].
+    ///
+    /// **Do not use this in attributes.**
+    ///
+    /// ```rust
+    /// use htmlize::EscapeTextWriter;
+    /// use std::io::Write;
+    /// # use assert2::assert;
+    ///
+    /// let mut writer = EscapeTextWriter::new(Vec::new());
+    /// writer.write_all(b"test: &<>").unwrap();
+    /// writer.write_all(b"\"'\xFF").unwrap();
+    /// assert!(writer.into_inner() == b"test: &amp;&lt;&gt;\"'\xFF");
+    /// ```
+    ///
+    /// This makes a lot of small writes to the underlying writer, so you may
+    /// want to wrap it in a [`std::io::BufWriter`].
+    pub struct EscapeTextWriter;
+
     {
         b'&' => b"&amp;",
         b'<' => b"&lt;",
@@ -176,6 +287,24 @@ escape_fn! {
     /// To work with `String` instead of bytes, see [`escape_attribute()`].
     pub fn escape_attribute_bytes;
 
+    /// Escape everything written to an [`io::Write`] to be used in a quoted
+    /// attribute, i.e. as if it was passed to [`escape_attribute_bytes()`].
+    ///
+    /// ```rust
+    /// use htmlize::EscapeAttributeWriter;
+    /// use std::io::Write;
+    /// # use assert2::assert;
+    ///
+    /// let mut writer = EscapeAttributeWriter::new(Vec::new());
+    /// writer.write_all(b"test: &<>").unwrap();
+    /// writer.write_all(b"\"'\xFF").unwrap();
+    /// assert!(writer.into_inner() == b"test: &amp;&lt;&gt;&quot;'\xFF");
+    /// ```
+    ///
+    /// This makes a lot of small writes to the underlying writer, so you may
+    /// want to wrap it in a [`std::io::BufWriter`].
+    pub struct EscapeAttributeWriter;
+
     {
         b'&' => b"&amp;",
         b'<' => b"&lt;",
@@ -222,6 +351,27 @@ escape_fn! {
     /// To work with `String` instead of bytes, see [`escape_all_quotes()`].
     pub fn escape_all_quotes_bytes;
 
+    /// Escape everything written to an [`io::Write`] including both single and
+    /// double quotes, i.e. as if it was passed to [`escape_all_quotes_bytes()`].
+    ///
+    /// Generally, it is safe to leave single quotes (apostrophes) unescaped, so you
+    /// should use [`EscapeTextWriter`] or [`EscapeAttributeWriter`].
+    ///
+    /// ```rust
+    /// use htmlize::EscapeAllQuotesWriter;
+    /// use std::io::Write;
+    /// # use assert2::assert;
+    ///
+    /// let mut writer = EscapeAllQuotesWriter::new(Vec::new());
+    /// writer.write_all(b"test: &<>").unwrap();
+    /// writer.write_all(b"\"'\xFF").unwrap();
+    /// assert!(writer.into_inner() == b"test: &amp;&lt;&gt;&quot;&apos;\xFF");
+    /// ```
+    ///
+    /// This makes a lot of small writes to the underlying writer, so you may
+    /// want to wrap it in a [`std::io::BufWriter`].
+    pub struct EscapeAllQuotesWriter;
+
     {
         b'&' => b"&amp;",
         b'<' => b"&lt;",
@@ -231,11 +381,685 @@ escape_fn! {
     }
 }
 
+feature! {
+    #![feature = "entities"]
+
+    /// Escape a string used in a text node, i.e. regular text, so that it
+    /// only contains ASCII.
+    ///
+    /// **Do not use this in attributes.**
+    ///
+    /// This escapes the same characters as [`escape_text()`], and also escapes
+    /// every non-ASCII character as a named entity. If a character has more
+    /// than one name, the preferred one is used, e.g. `&copy;` rather than
+    /// `&COPY;`. Characters without a name are escaped as hexadecimal numeric
+    /// entities.
+    ///
+    /// ```rust
+    /// use htmlize::escape_text_named;
+    /// # use assert2::assert;
+    ///
+    /// assert!(
+    ///     escape_text_named("© Björk & Борис 😀")
+    ///         == "&copy; Bj&ouml;rk &amp; &Bcy;&ocy;&rcy;&icy;&scy; &#x1F600;"
+    /// );
+    /// ```
+    ///
+    /// A few entities expand to two characters, e.g. `&nGt;` expands to
+    /// `"≫\u{20D2}"`. They are used when both characters appear together:
+    ///
+    /// ```rust
+    /// use htmlize::escape_text_named;
+    /// # use assert2::assert;
+    ///
+    /// assert!(escape_text_named("≫\u{20D2} ≫") == "&nGt; &gg;");
+    /// ```
+    ///
+    /// This is useful when the output must be ASCII, e.g. for systems that
+    /// mangle UTF-8. Otherwise, [`escape_text()`] produces shorter output.
+    pub fn escape_text_named<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+        escape_named(input.into(), b"&<>")
+    }
+
+    /// Escape a string to be used in a quoted attribute so that it only
+    /// contains ASCII.
+    ///
+    /// This escapes the same characters as [`escape_attribute()`], and also
+    /// escapes every non-ASCII character as a named entity. See
+    /// [`escape_text_named()`] for details.
+    ///
+    /// ```rust
+    /// use htmlize::escape_attribute_named;
+    /// # use assert2::assert;
+    ///
+    /// assert!(
+    ///     escape_attribute_named("“Björk” & \"Борис\"")
+    ///         == "&ldquo;Bj&ouml;rk&rdquo; &amp; &quot;&Bcy;&ocy;&rcy;&icy;&scy;&quot;"
+    /// );
+    /// ```
+    pub fn escape_attribute_named<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+        escape_named(input.into(), b"&<>\"")
+    }
+
+    /// Escape a string including both single and double quotes so that it
+    /// only contains ASCII.
+    ///
+    /// Generally, it is safe to leave single quotes (apostrophes) unescaped, so
+    /// you should use [`escape_text_named()`] or [`escape_attribute_named()`].
+    ///
+    /// ```rust
+    /// use htmlize::escape_all_quotes_named;
+    /// # use assert2::assert;
+    ///
+    /// assert!(
+    ///     escape_all_quotes_named("Björk's \"×\"")
+    ///         == "Bj&ouml;rk&apos;s &quot;&times;&quot;"
+    /// );
+    /// ```
+    pub fn escape_all_quotes_named<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+        escape_named(input.into(), b"&<>\"'")
+    }
+}
+
+/// Escape the bytes in `ascii` and all non-ASCII characters with entities.
+///
+/// Uses the first name in [`ENTITY_NAMES`](crate::entities::ENTITY_NAMES),
+/// and falls back to numeric entities.
+///
+/// # Panics
+///
+/// Panics if writing to a `String` fails. This should be impossible.
+#[cfg(feature = "entities")]
+fn escape_named<'a>(input: Cow<'a, str>, ascii: &[u8]) -> Cow<'a, str> {
+    use crate::entities::ENTITY_NAMES;
+    use std::fmt::Write;
+
+    let needs_escape = |c: char| !c.is_ascii() || ascii.contains(&(c as u8));
+    let preferred = |glyph: &str| ENTITY_NAMES.get(glyph)?.first().copied();
+
+    // Avoid allocating if nothing needs to be escaped.
+    let start = match input.find(needs_escape) {
+        Some(start) => start,
+        None => return input,
+    };
+
+    let mut output = String::with_capacity(input.len().saturating_mul(2));
+    output.push_str(&input[..start]);
+    let mut written = start;
+    let mut chars = input
+        .char_indices()
+        .skip_while(|&(i, _)| i < start)
+        .peekable();
+    while let Some((i, c)) = chars.next() {
+        if !needs_escape(c) {
+            continue;
+        }
+
+        output.push_str(&input[written..i]);
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "indices are within input"
+        )]
+        let end = i + c.len_utf8();
+
+        // Try entities that expand to two characters first.
+        if let Some(&(_, next)) = chars.peek() {
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "indices are within input"
+            )]
+            let pair_end = end + next.len_utf8();
+            if let Some(name) = preferred(&input[i..pair_end]) {
+                output.push_str(name);
+                chars.next();
+                written = pair_end;
+                continue;
+            }
+        }
+
+        match preferred(&input[i..end]) {
+            Some(name) => output.push_str(name),
+            None => write!(output, "&#x{:X};", u32::from(c)).unwrap(),
+        }
+        written = end;
+    }
+
+    output.push_str(&input[written..]);
+    output.into()
+}
+
+escape_table! {
+    escape_single_quoted_attribute {
+        b'&' => b"&amp;",
+        b'<' => b"&lt;",
+        b'>' => b"&gt;",
+        b'\'' => b"&apos;",
+    }
+}
+
+escape_table! {
+    escape_unquoted_attribute {
+        b'&' => b"&amp;",
+        b'<' => b"&lt;",
+        b'>' => b"&gt;",
+        b'"' => b"&quot;",
+        b'\'' => b"&apos;",
+        b'`' => b"&#96;",
+        b'=' => b"&#61;",
+        b'\t' => b"&#9;",
+        b'\n' => b"&#10;",
+        b'\x0C' => b"&#12;",
+        b'\r' => b"&#13;",
+        b' ' => b"&#32;",
+    }
+}
+
+/// Where escaped text will be inserted into HTML.
+///
+/// See [`escape_in()`] for usage.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EscapeContext {
+    /// Regular text, e.g. `<p>…</p>`. Same as [`escape_text()`].
+    Text,
+    /// An attribute value in double quotes, e.g. `<a title="…">`. Same as
+    /// [`escape_attribute()`].
+    DoubleQuotedAttribute,
+    /// An attribute value in single quotes, e.g. `<a title='…'>`.
+    SingleQuotedAttribute,
+    /// An attribute value without quotes, e.g. `<a title=…>`.
+    ///
+    /// This escapes whitespace, `=`, and `` ` `` in addition to `&`, `<`, `>`,
+    /// and both quotes.
+    ///
+    /// An empty value can’t be written without quotes: `<a title= >` doesn’t
+    /// give `title` an empty value; the parser skips the whitespace and uses
+    /// whatever comes next. So an empty string is escaped as `""`, which
+    /// gives `<a title="">`.
+    UnquotedAttribute,
+    /// The contents of an RCDATA element, i.e. `<title>` or `<textarea>`.
+    ///
+    /// Tags are not parsed inside these elements, but entities are, so this
+    /// needs the same escaping as regular text.
+    Rcdata,
+}
+
+/// Escape a string to be inserted into HTML in a given context.
+///
+/// This is useful when the context is only known at runtime. Each context
+/// escapes only what is needed to be safe there:
+///
+/// | [`EscapeContext`]        | `&` | `<` | `>` | `"` | `'` | `` ` `` `=` whitespace |
+/// |--------------------------|:---:|:---:|:---:|:---:|:---:|:----------------------:|
+/// | `Text`, `Rcdata`         |  ✓  |  ✓  |  ✓  |     |     |                        |
+/// | `DoubleQuotedAttribute`  |  ✓  |  ✓  |  ✓  |  ✓  |     |                        |
+/// | `SingleQuotedAttribute`  |  ✓  |  ✓  |  ✓  |     |  ✓  |                        |
+/// | `UnquotedAttribute`      |  ✓  |  ✓  |  ✓  |  ✓  |  ✓  |           ✓            |
+///
+/// ```rust
+/// use htmlize::{escape_in, EscapeContext};
+/// # use assert2::assert;
+///
+/// assert!(escape_in("a < b", EscapeContext::Text) == "a &lt; b");
+/// assert!(
+///     escape_in("Don't \"quote\"", EscapeContext::SingleQuotedAttribute)
+///         == "Don&apos;t \"quote\""
+/// );
+/// assert!(
+///     escape_in("a=b c", EscapeContext::UnquotedAttribute) == "a&#61;b&#32;c"
+/// );
+/// ```
+///
+/// To work with bytes (`[u8]`) instead of strings, see [`escape_bytes_in()`].
+///
+/// # Panics
+///
+/// Panics if the escaped bytes are invalid UTF-8. This should be impossible.
+pub fn escape_in<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+    context: EscapeContext,
+) -> Cow<'a, str> {
+    let input = input.into();
+
+    match escape_bytes_in_internal(input.as_bytes(), context) {
+        Some(output) => String::from_utf8(output).unwrap().into(),
+        None => input,
+    }
+}
+
+/// Escape a byte string to be inserted into HTML in a given context.
+///
+/// See [`escape_in()`] for details.
+///
+/// ```rust
+/// use htmlize::{escape_bytes_in, EscapeContext};
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_bytes_in(b"a=\xFF".as_slice(), EscapeContext::UnquotedAttribute)
+///         == b"a&#61;\xFF".as_slice()
+/// );
+/// ```
+///
+/// To work with `String` instead of bytes, see [`escape_in()`].
+pub fn escape_bytes_in<'a, S: Into<Cow<'a, [u8]>>>(
+    input: S,
+    context: EscapeContext,
+) -> Cow<'a, [u8]> {
+    let input = input.into();
+
+    match escape_bytes_in_internal(&input, context) {
+        Some(output) => output.into(),
+        None => input,
+    }
+}
+
+/// Escape `raw` for `context`, or return `None` if nothing needs escaping.
+fn escape_bytes_in_internal(
+    raw: &[u8],
+    context: EscapeContext,
+) -> Option<Vec<u8>> {
+    match context {
+        EscapeContext::Text | EscapeContext::Rcdata => {
+            escape_text_bytes_internal(raw)
+        }
+        EscapeContext::DoubleQuotedAttribute => {
+            escape_attribute_bytes_internal(raw)
+        }
+        EscapeContext::SingleQuotedAttribute => {
+            escape_single_quoted_attribute_bytes_internal(raw)
+        }
+        EscapeContext::UnquotedAttribute if raw.is_empty() => {
+            Some(EMPTY_UNQUOTED_ATTRIBUTE.to_vec())
+        }
+        EscapeContext::UnquotedAttribute => {
+            escape_unquoted_attribute_bytes_internal(raw)
+        }
+    }
+}
+
+/// An empty value for [`EscapeContext::UnquotedAttribute`]. The value can’t
+/// actually be empty, since then the parser would use whatever comes next.
+const EMPTY_UNQUOTED_ATTRIBUTE: &[u8] = b"\"\"";
+
+/// Escape a string literal used in a text node at compile time.
+///
+/// This works like [`escape_text()`], except that it takes a constant `&str`
+/// expression, e.g. a literal, and produces a `&'static str`. There is no cost
+/// at runtime.
+///
+/// **Do not use this in attributes.**
+///
+/// ```rust
+/// use htmlize::escape_text;
+/// # use assert2::assert;
+///
+/// const HEADER: &str = escape_text!("Tom & Jerry <3");
+/// assert!(HEADER == "Tom &amp; Jerry &lt;3");
+/// ```
+///
+/// To escape a whole file, see [`include_escaped!`](crate::include_escaped!).
+#[macro_export]
+macro_rules! escape_text {
+    ($input:expr $(,)?) => {
+        $crate::__escape_const!($input, $crate::EscapeContext::Text)
+    };
+}
+
+/// Escape a string literal used in a quoted attribute at compile time.
+///
+/// This works like [`escape_attribute()`], except that it takes a constant
+/// `&str` expression, e.g. a literal, and produces a `&'static str`. There is
+/// no cost at runtime.
+///
+/// ```rust
+/// use htmlize::escape_attribute;
+/// # use assert2::assert;
+///
+/// const TITLE: &str = escape_attribute!("Say \"cheese\" & smile");
+/// assert!(TITLE == "Say &quot;cheese&quot; &amp; smile");
+/// ```
+#[macro_export]
+macro_rules! escape_attribute {
+    ($input:expr $(,)?) => {
+        $crate::__escape_const!(
+            $input,
+            $crate::EscapeContext::DoubleQuotedAttribute
+        )
+    };
+}
+
+/// Include a file as a string escaped for a text node at compile time.
+///
+/// This is [`escape_text!`](crate::escape_text!) applied to [`include_str!`].
+/// The path is relative to the current file, just like with [`include_str!`].
+///
+/// **Do not use this in attributes.**
+///
+/// ```rust,ignore
+/// const FOOTER: &str = htmlize::include_escaped!("footer.txt");
+/// ```
+#[macro_export]
+macro_rules! include_escaped {
+    ($path:expr $(,)?) => {
+        $crate::escape_text!(::std::include_str!($path))
+    };
+}
+
+/// Implementation of [`escape_text!`](crate::escape_text!) and friends.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __escape_const {
+    ($input:expr, $context:expr) => {{
+        // These names are not hygienic, so they must not conflict with
+        // anything in `$input` or `$context`.
+        const __HTMLIZE_INPUT: &str = $input;
+        const __HTMLIZE_LEN: usize =
+            $crate::__escaped_len(__HTMLIZE_INPUT, $context);
+        const __HTMLIZE_BYTES: [u8; __HTMLIZE_LEN] =
+            $crate::__escape_const::<__HTMLIZE_LEN>(__HTMLIZE_INPUT, $context);
+        const __HTMLIZE_OUTPUT: &str =
+            match ::std::str::from_utf8(&__HTMLIZE_BYTES) {
+                Ok(output) => output,
+                Err(_) => panic!("escaped string is invalid UTF-8"),
+            };
+        __HTMLIZE_OUTPUT
+    }};
+}
+
+/// Get the entity for `c` in `context`, or an empty slice if `c` doesn’t need
+/// to be escaped.
+const fn context_map_u8(c: u8, context: EscapeContext) -> &'static [u8] {
+    match context {
+        EscapeContext::Text | EscapeContext::Rcdata => escape_text_map_u8(c),
+        EscapeContext::DoubleQuotedAttribute => escape_attribute_map_u8(c),
+        EscapeContext::SingleQuotedAttribute => {
+            escape_single_quoted_attribute_map_u8(c)
+        }
+        EscapeContext::UnquotedAttribute => escape_unquoted_attribute_map_u8(c),
+    }
+}
+
+/// Get the length of `input` once it’s escaped for `context`.
+///
+/// Used by [`escape_text!`](crate::escape_text!) and friends.
+///
+/// # Panics
+///
+/// Panics if the escaped length overflows `usize`. Entities are up to 6 times
+/// longer than the characters they replace, so this is possible for large
+/// inputs on 32-bit targets. In a `const` context this is a compile error.
+#[doc(hidden)]
+#[must_use]
+#[allow(
+    clippy::arithmetic_side_effects,
+    reason = "i < input.len(), so incrementing it can’t overflow"
+)]
+pub const fn __escaped_len(input: &str, context: EscapeContext) -> usize {
+    let input = input.as_bytes();
+    if input.is_empty() && matches!(context, EscapeContext::UnquotedAttribute) {
+        return EMPTY_UNQUOTED_ATTRIBUTE.len();
+    }
+
+    let mut len: usize = 0;
+    let mut i = 0;
+    while i < input.len() {
+        let add = match context_map_u8(input[i], context) {
+            [] => 1,
+            entity => entity.len(),
+        };
+        len = match len.checked_add(add) {
+            Some(len) => len,
+            None => panic!("escaped length overflows usize"),
+        };
+        i += 1;
+    }
+    len
+}
+
+/// Escape `input` for `context` into an array of exactly the right length.
+///
+/// Used by [`escape_text!`](crate::escape_text!) and friends. `N` must be the
+/// result of [`__escaped_len()`].
+///
+/// # Panics
+///
+/// Panics if `N` is not the escaped length of `input`.
+#[doc(hidden)]
+#[must_use]
+#[allow(
+    clippy::arithmetic_side_effects,
+    reason = "indices are bounded by the lengths of the arrays"
+)]
+pub const fn __escape_const<const N: usize>(
+    input: &str,
+    context: EscapeContext,
+) -> [u8; N] {
+    let input = input.as_bytes();
+    let mut output = [0; N];
+    if input.is_empty() && matches!(context, EscapeContext::UnquotedAttribute) {
+        assert!(
+            N == EMPTY_UNQUOTED_ATTRIBUTE.len(),
+            "escaped length is wrong"
+        );
+        let mut o = 0;
+        while o < N {
+            output[o] = EMPTY_UNQUOTED_ATTRIBUTE[o];
+            o += 1;
+        }
+        return output;
+    }
+
+    let mut i = 0;
+    let mut o = 0;
+    while i < input.len() {
+        match context_map_u8(input[i], context) {
+            [] => {
+                output[o] = input[i];
+                o += 1;
+            }
+            entity => {
+                let mut j = 0;
+                while j < entity.len() {
+                    output[o] = entity[j];
+                    o += 1;
+                    j += 1;
+                }
+            }
+        }
+        i += 1;
+    }
+    assert!(o == N, "escaped length is wrong");
+    output
+}
+
+/// A set of characters that can be represented in the output of
+/// [`escape_text_for()`] and friends.
+///
+/// Characters outside of the repertoire are escaped as hexadecimal numeric
+/// entities, e.g. `&#x1F600;`.
+#[derive(Clone, Copy, Debug)]
+pub enum Repertoire {
+    /// ASCII (U+0000 through U+007F).
+    Ascii,
+    /// ISO-8859-1, also known as Latin-1 (U+0000 through U+00FF).
+    Latin1,
+    /// Windows-1252, i.e. Latin-1 with most of the C1 control characters
+    /// replaced by printable characters like `€` and `“`.
+    ///
+    /// This follows the [WHATWG encoding spec][spec], so the five bytes that
+    /// Windows-1252 leaves undefined map to their C1 control characters.
+    ///
+    /// [spec]: https://encoding.spec.whatwg.org/#windows-1252
+    Windows1252,
+    /// The Basic Multilingual Plane (U+0000 through U+FFFF).
+    ///
+    /// This is useful for databases that can’t store 4 byte UTF-8, e.g.
+    /// `utf8mb3` columns, since it escapes emoji and other astral characters.
+    Bmp,
+    /// Characters for which a function returns `true`.
+    Custom(fn(char) -> bool),
+}
+
+impl Repertoire {
+    /// Check if `c` is in this repertoire.
+    ///
+    /// ```rust
+    /// use htmlize::Repertoire;
+    ///
+    /// assert!(Repertoire::Windows1252.contains('€'));
+    /// assert!(!Repertoire::Latin1.contains('€'));
+    /// assert!(Repertoire::Custom(char::is_alphabetic).contains('é'));
+    /// ```
+    #[must_use]
+    pub fn contains(self, c: char) -> bool {
+        match self {
+            Self::Ascii => c.is_ascii(),
+            Self::Latin1 => c <= '\u{FF}',
+            Self::Windows1252 => matches!(
+                c,
+                '\0'..='\u{7F}'
+                    | '\u{A0}'..='\u{FF}'
+                    | '\u{81}'
+                    | '\u{8D}'
+                    | '\u{8F}'
+                    | '\u{90}'
+                    | '\u{9D}'
+                    | 'Œ' | 'œ' | 'Š' | 'š' | 'Ÿ' | 'Ž' | 'ž' | 'ƒ'
+                    | 'ˆ' | '˜' | '–' | '—' | '‘' | '’' | '‚' | '“'
+                    | '”' | '„' | '†' | '‡' | '•' | '…' | '‰' | '‹'
+                    | '›' | '€' | '™'
+            ),
+            Self::Bmp => c <= '\u{FFFF}',
+            Self::Custom(contains) => contains(c),
+        }
+    }
+}
+
+/// Escape a string used in a text node, i.e. regular text, so that it only
+/// contains characters in `repertoire`.
+///
+/// **Do not use this in attributes.**
+///
+/// This escapes the same characters as [`escape_text()`], and also escapes
+/// every character outside of `repertoire` as a hexadecimal numeric entity.
+///
+/// ```rust
+/// use htmlize::{escape_text_for, Repertoire};
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_text_for("<Björk> €5 😀", Repertoire::Latin1)
+///         == "&lt;Björk&gt; &#x20AC;5 &#x1F600;"
+/// );
+/// assert!(escape_text_for("<Björk> €5 😀", Repertoire::Bmp) == "&lt;Björk&gt; €5 &#x1F600;");
+/// ```
+///
+/// If nothing needs to be escaped, this returns the input without allocating.
+pub fn escape_text_for<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+    repertoire: Repertoire,
+) -> Cow<'a, str> {
+    escape_for(input.into(), repertoire, escape_text_map_u8)
+}
+
+/// Escape a string to be used in a quoted attribute so that it only contains
+/// characters in `repertoire`.
+///
+/// This escapes the same characters as [`escape_attribute()`], and also
+/// escapes every character outside of `repertoire` as a hexadecimal numeric
+/// entity.
+///
+/// ```rust
+/// use htmlize::{escape_attribute_for, Repertoire};
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_attribute_for("“Björk” & \"Борис\"", Repertoire::Windows1252)
+///         == "“Björk” &amp; &quot;&#x411;&#x43E;&#x440;&#x438;&#x441;&quot;"
+/// );
+/// ```
+pub fn escape_attribute_for<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+    repertoire: Repertoire,
+) -> Cow<'a, str> {
+    escape_for(input.into(), repertoire, escape_attribute_map_u8)
+}
+
+/// Escape a string including both single and double quotes so that it only
+/// contains characters in `repertoire`.
+///
+/// Generally, it is safe to leave single quotes (apostrophes) unescaped, so you
+/// should use [`escape_text_for()`] or [`escape_attribute_for()`].
+///
+/// ```rust
+/// use htmlize::{escape_all_quotes_for, Repertoire};
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_all_quotes_for("Björk's \"×\"", Repertoire::Ascii)
+///         == "Bj&#xF6;rk&apos;s &quot;&#xD7;&quot;"
+/// );
+/// ```
+pub fn escape_all_quotes_for<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+    repertoire: Repertoire,
+) -> Cow<'a, str> {
+    escape_for(input.into(), repertoire, escape_all_quotes_map_u8)
+}
+
+/// Escape ASCII characters with `map_u8()`, and characters outside of
+/// `repertoire` with numeric entities.
+///
+/// # Panics
+///
+/// Panics if writing to a `String` fails. This should be impossible.
+fn escape_for(
+    input: Cow<'_, str>,
+    repertoire: Repertoire,
+    map_u8: fn(u8) -> &'static [u8],
+) -> Cow<'_, str> {
+    use std::fmt::Write;
+
+    let entity = |c: char| -> Option<&'static [u8]> {
+        if c.is_ascii() {
+            #[allow(clippy::cast_possible_truncation, reason = "c is ASCII")]
+            let entity = map_u8(c as u8);
+            if !entity.is_empty() {
+                return Some(entity);
+            }
+        }
+        None
+    };
+    let needs_escape = |c: char| entity(c).is_some() || !repertoire.contains(c);
+
+    // Avoid allocating if nothing needs to be escaped.
+    let start = match input.find(needs_escape) {
+        Some(start) => start,
+        None => return input,
+    };
+
+    let mut output = String::with_capacity(input.len().saturating_mul(2));
+    output.push_str(&input[..start]);
+    for c in input[start..].chars() {
+        if let Some(entity) = entity(c) {
+            // Entities from `map_u8()` are always ASCII.
+            output.push_str(std::str::from_utf8(entity).unwrap());
+        } else if repertoire.contains(c) {
+            output.push(c);
+        } else {
+            write!(output, "&#x{:X};", u32::from(c)).unwrap();
+        }
+    }
+
+    output.into()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use assert2::assert;
     use pastey::paste;
+    use std::io::Write;
 
     macro_rules! test {
         ($name:ident, $($test:tt)+) => {
@@ -272,10 +1096,34 @@ mod tests {
                     [<escape_all_quotes_bytes_ $name>],
                     escape_all_quotes_bytes($in.as_bytes()) == $out.as_bytes()
                 );
+                test!(
+                    [<escape_text_writer_ $name>],
+                    write_all(EscapeTextWriter::new(Vec::new()), $in)
+                        .into_inner()
+                        == $out.as_bytes()
+                );
+                test!(
+                    [<escape_attribute_writer_ $name>],
+                    write_all(EscapeAttributeWriter::new(Vec::new()), $in)
+                        .into_inner()
+                        == $out.as_bytes()
+                );
+                test!(
+                    [<escape_all_quotes_writer_ $name>],
+                    write_all(EscapeAllQuotesWriter::new(Vec::new()), $in)
+                        .into_inner()
+                        == $out.as_bytes()
+                );
             }
         };
     }
 
+    /// Write `input` to `writer` with `write_all()` and return `writer`.
+    fn write_all<W: Write>(mut writer: W, input: &str) -> W {
+        writer.write_all(input.as_bytes()).unwrap();
+        writer
+    }
+
     test_all!(none, "", "");
     test_all!(clean, "clean", "clean");
     test_all!(lt_gt, "< >", "&lt; &gt;");
@@ -359,4 +1207,244 @@ mod tests {
         escape_all_quotes_bytes_invalid_utf8,
         escape_all_quotes_bytes(&b"\xa1"[..]) == &b"\xa1"[..]
     );
+
+    test!(
+        escape_text_writer_dirty_html,
+        write_all(EscapeTextWriter::new(Vec::new()), HTML_DIRTY).into_inner()
+            == HTML_DIRTY_ESCAPED.as_bytes()
+    );
+
+    #[test]
+    fn escape_attribute_writer_one_byte_at_a_time() {
+        let mut writer = EscapeAttributeWriter::new(Vec::new());
+        for byte in b"a<\"b\"&c" {
+            assert!(writer.write(&[*byte]).unwrap() == 1);
+        }
+        writer.flush().unwrap();
+        assert!(writer.get_ref() == b"a&lt;&quot;b&quot;&amp;c");
+    }
+
+    #[test]
+    fn escape_text_writer_partial_write() {
+        let mut writer = EscapeTextWriter::new(Vec::new());
+
+        // Plain text up to the first special byte is written as is.
+        assert!(writer.write(b"ab<cd").unwrap() == 2);
+        // A special byte is written as a complete entity.
+        assert!(writer.write(b"<cd").unwrap() == 1);
+        assert!(writer.write(b"cd").unwrap() == 2);
+
+        assert!(writer.into_inner() == b"ab&lt;cd");
+    }
+
+    const ALL_CONTEXTS: [EscapeContext; 5] = [
+        EscapeContext::Text,
+        EscapeContext::DoubleQuotedAttribute,
+        EscapeContext::SingleQuotedAttribute,
+        EscapeContext::UnquotedAttribute,
+        EscapeContext::Rcdata,
+    ];
+
+    #[test]
+    fn escape_text_macro() {
+        const EMPTY: &str = escape_text!("");
+        const CLEAN: &str = escape_text!("clean");
+        const INPUT: &str = "test: &<>\"'é×😀";
+        assert!(EMPTY == "");
+        assert!(CLEAN == "clean");
+        assert!(escape_text!(INPUT) == escape_text(INPUT));
+        assert!(escape_text!("&&&") == "&amp;&amp;&amp;");
+    }
+
+    #[test]
+    fn escape_attribute_macro() {
+        const INPUT: &str = "test: &<>\"'é×😀";
+        assert!(escape_attribute!(INPUT) == escape_attribute(INPUT));
+        assert!(escape_attribute!(concat!("\"", "a", "\"")) == "&quot;a&quot;");
+    }
+
+    #[test]
+    fn include_escaped_macro() {
+        const ESCAPED: &str = include_escaped!("escape.rs");
+        assert!(ESCAPED == escape_text(include_str!("escape.rs")));
+    }
+
+    #[test]
+    fn escape_const_every_context() {
+        let input = "a=\"b\" & 'c'\t<d>`";
+        for context in ALL_CONTEXTS {
+            assert!(
+                __escaped_len(input, context)
+                    == escape_in(input, context).len(),
+                "{context:?}"
+            );
+        }
+
+        let escaped: [u8; 25] =
+            __escape_const("a='b c'", EscapeContext::UnquotedAttribute);
+        assert!(escaped == *b"a&#61;&apos;b&#32;c&apos;");
+    }
+
+    #[test]
+    fn escape_in_empty_unquoted_attribute() {
+        assert!(escape_in("", EscapeContext::UnquotedAttribute) == "\"\"");
+        assert!(
+            escape_bytes_in(&b""[..], EscapeContext::UnquotedAttribute)
+                == &b"\"\""[..]
+        );
+        assert!(__escaped_len("", EscapeContext::UnquotedAttribute) == 2);
+        let escaped: [u8; 2] =
+            __escape_const("", EscapeContext::UnquotedAttribute);
+        assert!(escaped == *b"\"\"");
+
+        // Other contexts can be empty.
+        assert!(escape_in("", EscapeContext::SingleQuotedAttribute) == "");
+    }
+
+    #[test]
+    #[should_panic(expected = "escaped length is wrong")]
+    fn escape_const_wrong_length() {
+        let _: [u8; 9] = __escape_const("&", EscapeContext::Text);
+    }
+
+    #[test]
+    fn escape_in_clean_is_borrowed() {
+        for context in ALL_CONTEXTS {
+            assert!(matches!(escape_in("clean", context), Cow::Borrowed(_)));
+            assert!(matches!(
+                escape_bytes_in(b"clean".as_slice(), context),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+
+    #[test]
+    fn escape_in_matches_escape_fns() {
+        for input in ["", "&<>\"'", HTML_DIRTY] {
+            assert!(
+                escape_in(input, EscapeContext::Text) == escape_text(input)
+            );
+            assert!(
+                escape_in(input, EscapeContext::Rcdata) == escape_text(input)
+            );
+            assert!(
+                escape_in(input, EscapeContext::DoubleQuotedAttribute)
+                    == escape_attribute(input)
+            );
+        }
+    }
+
+    test!(
+        escape_in_single_quoted_attribute,
+        escape_in("&<>\"'", EscapeContext::SingleQuotedAttribute)
+            == "&amp;&lt;&gt;\"&apos;"
+    );
+    test!(
+        escape_in_unquoted_attribute,
+        escape_in("&<>\"'`= \t\n\x0C\r", EscapeContext::UnquotedAttribute)
+            == "&amp;&lt;&gt;&quot;&apos;&#96;&#61;&#32;&#9;&#10;&#12;&#13;"
+    );
+    test!(
+        escape_bytes_in_invalid_utf8,
+        escape_bytes_in(&b"\xa1 "[..], EscapeContext::UnquotedAttribute)
+            == &b"\xa1&#32;"[..]
+    );
+
+    #[test]
+    fn escape_for_clean_is_borrowed() {
+        assert!(matches!(
+            escape_text_for("Björk \"€\"", Repertoire::Windows1252),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn escape_for_dirty_is_owned() {
+        assert!(matches!(
+            escape_attribute_for("😀 'a'", Repertoire::Bmp),
+            Cow::Owned(_)
+        ));
+    }
+
+    test!(
+        escape_text_for_ascii,
+        escape_text_for("a&b é 😀", Repertoire::Ascii)
+            == "a&amp;b &#xE9; &#x1F600;"
+    );
+    test!(
+        escape_text_for_latin1_c1,
+        escape_text_for("\u{80}€", Repertoire::Latin1) == "\u{80}&#x20AC;"
+    );
+    test!(
+        escape_text_for_windows1252_c1,
+        escape_text_for("\u{80}\u{81}€ž", Repertoire::Windows1252)
+            == "&#x80;\u{81}€ž"
+    );
+    test!(
+        escape_text_for_bmp,
+        escape_text_for("\u{FFFF}\u{10000}", Repertoire::Bmp)
+            == "\u{FFFF}&#x10000;"
+    );
+    test!(
+        escape_all_quotes_for_custom,
+        escape_all_quotes_for("a1 'b'", Repertoire::Custom(|c| c != '1'))
+            == "a&#x31; &apos;b&apos;"
+    );
+
+    #[cfg(feature = "entities")]
+    mod named {
+        use super::*;
+        use assert2::assert;
+
+        #[test]
+        fn clean_is_borrowed() {
+            assert!(matches!(
+                escape_attribute_named("clean 'text'"),
+                Cow::Borrowed("clean 'text'")
+            ));
+        }
+
+        #[test]
+        fn ascii_specials() {
+            assert!(escape_text_named("&<>\"'") == "&amp;&lt;&gt;\"'");
+            assert!(escape_attribute_named("&<>\"'") == "&amp;&lt;&gt;&quot;'");
+            assert!(
+                escape_all_quotes_named("&<>\"'")
+                    == "&amp;&lt;&gt;&quot;&apos;"
+            );
+        }
+
+        #[test]
+        fn preferred_names() {
+            assert!(escape_text_named("\u{a0}") == "&nbsp;");
+            assert!(escape_text_named("\u{27E8}") == "&lang;");
+            assert!(escape_text_named("Ö ö") == "&Ouml; &ouml;");
+        }
+
+        #[test]
+        fn numeric_fallback() {
+            assert!(escape_text_named("a😀b") == "a&#x1F600;b");
+            assert!(escape_text_named("\u{80}") == "&#x80;");
+        }
+
+        #[test]
+        fn pairs() {
+            assert!(escape_text_named("<\u{20D2}") == "&nvlt;");
+            assert!(escape_text_named("\u{22D9}\u{338}") == "&nGg;");
+            // Unescaped ASCII is never combined with the next character.
+            assert!(escape_text_named("=\u{20E5}") == "=&#x20E5;");
+            assert!(escape_text_named("fj") == "fj");
+        }
+
+        #[test]
+        fn all_entities_are_ascii() {
+            const ALL_EXPANDED: &str =
+                include_str!("../tests/corpus/all-entities-expanded.txt");
+            let escaped = escape_all_quotes_named(ALL_EXPANDED);
+            assert!(escaped.is_ascii());
+
+            #[cfg(any(feature = "unescape", feature = "unescape_fast"))]
+            assert!(crate::unescape(escaped) == ALL_EXPANDED);
+        }
+    }
 }
@@ -38,6 +38,43 @@ assert!(htmlize::unescape("3 &times 4 &gt; 10") == "3 × 4 > 10");
 //! You should almost never need [`escape_all_quotes()`], but it’s included
 //! because sometimes it’s convenient to wrap attribute values in single quotes.
 //!
+//! If the context is only known at runtime, use [`escape_in()`] or
+//! [`escape_bytes_in()`] with an [`EscapeContext`]. They also handle unquoted
+//! attributes and RCDATA elements like `<title>`.
+//!
+//! None of those work inside a `<script>` element, since entities aren’t
+//! expanded there. Use [`escape_script_data()`] to embed JSON or JavaScript
+//! strings in a `<script>`.
+//!
+//! Likewise, use [`escape_comment()`] for text inside an HTML comment.
+//!
+//! For XML 1.0, e.g. RSS or SVG, use [`escape_xml_text()`] and
+//! [`escape_xml_attribute()`] to escape, and [`unescape_xml()`] to unescape.
+//! XML only has five predefined entities and requires semicolons, so the HTML
+//! functions aren’t correct there. These don’t require any features.
+//!
+//! To escape string literals at compile time, use the [`escape_text!`] and
+//! [`escape_attribute!`] macros. [`include_escaped!`] includes a file escaped
+//! as text. They produce a `&'static str` and have no cost at runtime.
+//!
+//! To escape output as it is written rather than all at once, use the
+//! [`io::Write`](std::io::Write) adapters: [`EscapeTextWriter`],
+//! [`EscapeAttributeWriter`], and [`EscapeAllQuotesWriter`].
+//!
+//! To produce output that only uses a limited set of characters, e.g. for a
+//! database that can’t store emoji, use [`escape_text_for()`],
+//! [`escape_attribute_for()`], or [`escape_all_quotes_for()`] with a
+//! [`Repertoire`].
+#![cfg_attr(
+    feature = "entities",
+    doc = r"
+To produce ASCII-only output, e.g. for systems that mangle UTF-8, use
+[`escape_text_named()`], [`escape_attribute_named()`], or
+[`escape_all_quotes_named()`]. They also escape non-ASCII characters as named
+entities.
+"
+)]
+//!
 //! # Which `unescape` function to use
 //!
 //! All `unescape` functions require the `unescape` or `unescape_fast` feature
@@ -54,6 +91,63 @@ assert!(htmlize::unescape("3 &times 4 &gt; 10") == "3 × 4 > 10");
 //! [`unescape_bytes_in()`] is just like [`unescape_in()`] except that it works
 //! on `[u8]` rather than strings.
 //!
+//! To avoid allocating, e.g. when unescaping many short strings, use
+//! [`unescape_in_place()`] or [`unescape_bytes_in_place()`]. They overwrite
+//! the input with the result.
+//!
+//! To unescape input that arrives in chunks, use [`Unescaper`]. It correctly
+//! handles entities that are split across chunks.
+//!
+//! To find malformed references, e.g. to show errors to an author, use
+//! [`unescape_with_diagnostics()`]. To reject input with malformed references
+//! instead of recovering from them, use [`try_unescape_in()`].
+//!
+//! To deviate from the spec, e.g. to match entity names ignoring case or to
+//! only expand entities with semicolons, use [`UnescapeOptions`].
+//!
+//! To expand only some references, e.g. to make stored HTML readable while
+//! leaving `&lt;` and `&amp;` escaped, use [`unescape_selective()`] with a
+//! [`Filter`].
+//!
+//! To expand references that aren’t standard entities, e.g. placeholders like
+//! `&username;` in a template, use [`unescape_with_fallback()`]. It calls a
+//! closure with the name of each unknown reference.
+//!
+//! To compare or hash escaped text from different sources, use
+//! [`normalize_escaped()`]. It rewrites text so that equivalent spellings like
+//! `&#x26;`, `&AMP;`, and `&amp;` all come out the same.
+//!
+//! To unescape with your own set of named entities, implement
+//! [`EntityMatcher`] and use [`unescape_with()`] or [`unescape_bytes_with()`].
+#![cfg_attr(
+    feature = "entities",
+    doc = r"
+To add or remove named entities at runtime, e.g. for a legacy system with its
+own entities, use an [`EntityTable`]. It can also load entities declared in an
+SGML or XML DTD with [`EntityTable::load_dtd()`].
+"
+)]
+#![cfg_attr(
+    feature = "entities",
+    doc = r"
+# Entity information
+
+To list named entities, e.g. for an entity picker, use [`all_entities()`]. It
+returns an [`Entity`] with the name, code points, and glyph of each entity. To
+look up a single entity by name, use [`lookup_entity()`], or to autocomplete
+a partial name, use [`entities_with_prefix()`]. To find the names of
+entities that expand to some text, use [`entity_names_for_char()`] or
+[`entity_names_for_str()`].
+
+To use an entity’s glyph in a constant, use the [`entity!`] macro. It checks
+the name at compile time.
+
+To suggest corrections for a misspelled entity like `&nbps;`, use
+[`suggest_entities()`]. With `unescape`, `Diagnostic::suggestions()` does the
+same for a problem found by `unescape_with_diagnostics()`.
+"
+)]
+//!
 //! # Features
 //!
 //! The `escape` functions are all available with no features enabled.
@@ -69,8 +163,29 @@ assert!(htmlize::unescape("3 &times 4 &gt; 10") == "3 × 4 > 10");
 //!   * `unescape`: provide normal version of [`unescape()`]. This will
 //!     automatically enable the `entities` feature.
 //!
-//!   * `entities`: build [`ENTITIES`] map. Enabling this will add a dependency
-//!     on [phf] and may slow builds by a few seconds.
+//!   * `entities`: build [`ENTITIES`] map and provide [`escape_text_named()`]
+//!     and friends. Enabling this will add dependencies on [phf] and
+//!     [serde_json] (for [`EntityTable::load_json()`]) and may slow builds by a
+//!     few seconds.
+//!
+//! ### Custom entities at build time
+//!
+//! The entities used by the `unescape_fast`, `unescape`, and `entities`
+//! features are generated from [entities.json] when htmlize is built. To use a
+//! different set, set `HTMLIZE_ENTITIES_JSON` to the absolute path of a file in
+//! the same format. To add entities to the standard set, set
+//! `HTMLIZE_EXTRA_ENTITIES_JSON` to one or more absolute paths, separated like
+//! `PATH`. Later files replace entities with the same name. For example, in
+//! `.cargo/config.toml`:
+//!
+//! ```toml
+//! [env]
+//! HTMLIZE_EXTRA_ENTITIES_JSON = { value = "docbook-entities.json", relative = true }
+//! ```
+//!
+//! Names must be `&` followed by ASCII letters and digits, optionally ending
+//! with `;`. An entity without a `;`, e.g. `&logo`, must also be defined with
+//! one, e.g. `&logo;`. The build fails if these rules are broken.
 //!
 //! ### Internal features
 //!
@@ -92,7 +207,9 @@ assert!(htmlize::unescape("3 &times 4 &gt; 10") == "3 × 4 > 10");
 //! increases in the MSRV will require a major version bump.
 //!
 //! [official WHATWG spec]: https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+//! [entities.json]: https://html.spec.whatwg.org/entities.json
 //! [phf]: https://crates.io/crates/phf
+//! [serde_json]: https://crates.io/crates/serde_json
 //! [iai]: https://crates.io/crates/iai
 //! [benchmarks]: https://github.com/danielparks/htmlize#benchmarks
 
@@ -120,6 +237,15 @@ macro_rules! feature {
 mod escape;
 pub use escape::*;
 
+mod comment;
+pub use comment::*;
+
+mod script;
+pub use script::*;
+
+mod xml;
+pub use xml::*;
+
 #[cfg(all(feature = "bench", not(doc)))]
 pub mod unescape;
 
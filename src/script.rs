@@ -0,0 +1,193 @@
+//! # Escape text for use inside a `<script>` element
+
+use std::borrow::Cow;
+use std::fmt;
+
+/// An error returned by [`escape_script_data()`].
+///
+/// The input contained a sequence that needed to be escaped, but it was
+/// preceded by a backslash. There is no way to escape it without changing the
+/// meaning of the script, e.g. `\</script>` would become `\\u003C/script>`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ScriptDataError {
+    /// The byte offset of the sequence that could not be escaped.
+    pub offset: usize,
+}
+
+impl fmt::Display for ScriptDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot escape script data at byte {} because it follows a \
+            backslash",
+            self.offset
+        )
+    }
+}
+
+impl std::error::Error for ScriptDataError {}
+
+/// Escape a string to be used inside a `<script>` element, e.g. a JSON
+/// payload.
+///
+/// Entities are not expanded inside `<script>`, so none of the other `escape`
+/// functions work there. Instead, this replaces the `<` in `</script`, `<!--`,
+/// and `<script` (ignoring case) with the JavaScript escape `\u003C`. Those are
+/// the only sequences that can end the element early or change how its end is
+/// found.
+///
+/// It also replaces U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR with
+/// `\u2028` and `\u2029`, since JavaScript engines older than ES2019 treat
+/// them as line breaks even inside strings.
+///
+/// ```rust
+/// use htmlize::escape_script_data;
+/// # use assert2::assert;
+///
+/// let json = r#"{"html": "<script>alert(1)</SCRIPT>", "ok": "a < b"}"#;
+/// assert!(
+///     escape_script_data(json).unwrap()
+///         == r#"{"html": "\u003Cscript>alert(1)\u003C/SCRIPT>", "ok": "a < b"}"#
+/// );
+/// ```
+///
+/// **JavaScript escapes only work inside string literals**, so this is only
+/// safe if every replaced sequence is inside a string, as it always is in
+/// JSON.
+///
+/// # Errors
+///
+/// Returns [`ScriptDataError`] if a sequence that needs to be escaped follows
+/// an odd number of backslashes, since escaping it would change its meaning:
+///
+/// ```rust
+/// use htmlize::escape_script_data;
+/// # use assert2::assert;
+///
+/// let error = escape_script_data(r#"x = "\</script>";"#).unwrap_err();
+/// assert!(error.offset == 6);
+/// ```
+pub fn escape_script_data<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+) -> Result<Cow<'a, str>, ScriptDataError> {
+    let input = input.into();
+    let raw = input.as_bytes();
+
+    let mut output = String::new();
+    let mut written = 0;
+    let mut start = 0;
+    while let Some(i) = memchr::memchr2(b'<', 0xE2, &raw[start..]) {
+        #[allow(clippy::arithmetic_side_effects, reason = "i < raw.len()")]
+        let i = start + i;
+        #[allow(clippy::arithmetic_side_effects, reason = "i < raw.len()")]
+        let next = i + 1;
+        start = next;
+
+        let (escape, len) = match &raw[i..] {
+            [b'<', rest @ ..] if needs_escape(rest) => ("\\u003C", 1),
+            [0xE2, 0x80, 0xA8, ..] => ("\\u2028", 3),
+            [0xE2, 0x80, 0xA9, ..] => ("\\u2029", 3),
+            _ => continue,
+        };
+
+        if is_escaped(&raw[..i]) {
+            return Err(ScriptDataError { offset: i });
+        }
+
+        output.push_str(&input[written..i]);
+        output.push_str(escape);
+        #[allow(clippy::arithmetic_side_effects, reason = "len is in raw")]
+        let end = i + len;
+        written = end;
+        start = end;
+    }
+
+    if written == 0 {
+        // Nothing was escaped, since every escape replaces at least one byte.
+        Ok(input)
+    } else {
+        output.push_str(&input[written..]);
+        Ok(output.into())
+    }
+}
+
+/// Check if the bytes after a `<` make it the start of `</script`, `<!--`, or
+/// `<script`, ignoring case.
+fn needs_escape(rest: &[u8]) -> bool {
+    let starts_with = |prefix: &[u8]| {
+        rest.get(..prefix.len())
+            .map_or(false, |head| head.eq_ignore_ascii_case(prefix))
+    };
+    starts_with(b"/script") || starts_with(b"!--") || starts_with(b"script")
+}
+
+/// Check if `before` ends with an odd number of backslashes, i.e. if the next
+/// character is escaped.
+fn is_escaped(before: &[u8]) -> bool {
+    before.iter().rev().take_while(|&&c| c == b'\\').count() % 2 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    macro_rules! test {
+        ($name:ident, $input:expr, $expected:expr) => {
+            #[test]
+            fn $name() {
+                assert!(escape_script_data($input).unwrap() == $expected);
+            }
+        };
+    }
+
+    test!(empty, "", "");
+    test!(clean, "a < b && c > d", "a < b && c > d");
+    test!(end_tag, "</script>", "\\u003C/script>");
+    test!(end_tag_case, "</ScRiPt >", "\\u003C/ScRiPt >");
+    test!(start_tag, "<script>", "\\u003Cscript>");
+    test!(comment, "<!-- -->", "\\u003C!-- -->");
+    test!(partial, "</scrip <!- <scrip", "</scrip <!- <scrip");
+    test!(at_end, "a</script", "a\\u003C/script");
+    test!(separators, "a\u{2028}b\u{2029}c", "a\\u2028b\\u2029c");
+    test!(other_e2, "“a” – b", "“a” – b");
+    test!(
+        even_backslashes,
+        r"\\</script>\\\\<!--",
+        r"\\\u003C/script>\\\\\u003C!--"
+    );
+
+    #[test]
+    fn clean_is_borrowed() {
+        assert!(matches!(
+            escape_script_data("if (a < b) {}"),
+            Ok(Cow::Borrowed(_))
+        ));
+    }
+
+    #[test]
+    fn odd_backslashes() {
+        assert!(
+            escape_script_data(r"ok \\ </script \<!--").unwrap_err()
+                == ScriptDataError { offset: 16 }
+        );
+        assert!(
+            escape_script_data("\\\u{2028}").unwrap_err()
+                == ScriptDataError { offset: 1 }
+        );
+    }
+
+    #[test]
+    fn unescaped_backslash_elsewhere() {
+        assert!(escape_script_data(r"\<b>").unwrap() == r"\<b>");
+    }
+
+    #[test]
+    fn error_display() {
+        assert!(
+            ScriptDataError { offset: 3 }.to_string()
+                == "cannot escape script data at byte 3 because it follows a \
+                backslash"
+        );
+    }
+}
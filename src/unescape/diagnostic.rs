@@ -0,0 +1,698 @@
+//! Report parse errors in character references while unescaping.
+
+use super::{internal, Context, DefaultMatcher};
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::fmt;
+use std::ops::Range;
+
+/// A kind of parse error in a character reference.
+///
+/// Most of these are [parse errors defined by the WHATWG spec][errors], and
+/// [`name()`](Self::name) returns the name used there. Browsers recover from
+/// all of them, and so does [`unescape_in()`](super::unescape_in), but they
+/// usually indicate a mistake in the input.
+///
+/// [errors]: https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// A reference was not terminated with `;`, e.g. `&amp` or `&#38`.
+    MissingSemicolonAfterCharacterReference,
+    /// `&` followed by alphanumeric characters and `;` that doesn’t match any
+    /// entity, e.g. `&foo;`.
+    UnknownNamedCharacterReference,
+    /// `&#` or `&#x` not followed by any digits, e.g. `&#;` or `&#xZ`.
+    AbsenceOfDigitsInNumericCharacterReference,
+    /// A numeric reference to U+0000, e.g. `&#0;`.
+    NullCharacterReference,
+    /// A numeric reference above U+10FFFF, e.g. `&#x110000;`.
+    CharacterReferenceOutsideUnicodeRange,
+    /// A numeric reference to a surrogate, e.g. `&#xD800;`.
+    SurrogateCharacterReference,
+    /// A numeric reference to a noncharacter, e.g. `&#xFFFF;`.
+    NoncharacterCharacterReference,
+    /// A numeric reference to a control character other than ASCII
+    /// whitespace, e.g. `&#x80;` or `&#13;`.
+    ControlCharacterReference,
+    /// `&` followed by alphanumeric characters that were not expanded and are
+    /// not followed by `;`, e.g. `&foo` or `&timesx` in an attribute.
+    ///
+    /// This is not a parse error according to the spec, but it’s likely to be
+    /// a mistake.
+    AmbiguousAmpersand,
+}
+
+impl ParseErrorKind {
+    /// The name of the parse error in the WHATWG spec, e.g.
+    /// `"missing-semicolon-after-character-reference"`.
+    ///
+    /// [`ParseErrorKind::AmbiguousAmpersand`] is not a parse error in the
+    /// spec, so its name is `"ambiguous-ampersand"`.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::MissingSemicolonAfterCharacterReference => {
+                "missing-semicolon-after-character-reference"
+            }
+            Self::UnknownNamedCharacterReference => {
+                "unknown-named-character-reference"
+            }
+            Self::AbsenceOfDigitsInNumericCharacterReference => {
+                "absence-of-digits-in-numeric-character-reference"
+            }
+            Self::NullCharacterReference => "null-character-reference",
+            Self::CharacterReferenceOutsideUnicodeRange => {
+                "character-reference-outside-unicode-range"
+            }
+            Self::SurrogateCharacterReference => {
+                "surrogate-character-reference"
+            }
+            Self::NoncharacterCharacterReference => {
+                "noncharacter-character-reference"
+            }
+            Self::ControlCharacterReference => "control-character-reference",
+            Self::AmbiguousAmpersand => "ambiguous-ampersand",
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A parse error found while unescaping.
+///
+/// See [`unescape_with_diagnostics()`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Diagnostic {
+    /// What’s wrong with the reference.
+    pub kind: ParseErrorKind,
+    /// The byte range of the reference in the input.
+    pub range: Range<usize>,
+    /// The text of the reference, e.g. `"&amp"`.
+    pub text: String,
+}
+
+impl Diagnostic {
+    /// Suggest named entities that the reference might have been meant to be.
+    ///
+    /// This returns [`suggest_entities()`](crate::suggest_entities) for
+    /// unknown named references, ambiguous ampersands, and named references
+    /// without a semicolon. It returns an empty `Vec` for other errors.
+    ///
+    /// ```rust
+    /// use htmlize::{unescape_with_diagnostics, Context};
+    /// # use assert2::assert;
+    ///
+    /// let (_, diagnostics) =
+    ///     unescape_with_diagnostics("&nbps; &Copy; &hellip", Context::General);
+    /// let suggestions: Vec<_> = diagnostics
+    ///     .iter()
+    ///     .map(|diagnostic| diagnostic.suggestions()[0])
+    ///     .collect();
+    /// assert!(suggestions == ["&nbsp;", "&copy;", "&hellip;"]);
+    /// ```
+    #[cfg(feature = "entities")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "entities")))]
+    #[must_use]
+    pub fn suggestions(&self) -> Vec<&'static str> {
+        match self.kind {
+            ParseErrorKind::UnknownNamedCharacterReference
+            | ParseErrorKind::AmbiguousAmpersand => {
+                crate::suggest_entities(&self.text)
+            }
+            ParseErrorKind::MissingSemicolonAfterCharacterReference
+                if !self.text.starts_with("&#") =>
+            {
+                crate::suggest_entities(&self.text)
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at bytes {}..{}: {:?}",
+            self.kind, self.range.start, self.range.end, self.text
+        )
+    }
+}
+
+/// Expand all valid entities in a given context and report parse errors.
+///
+/// This expands entities exactly like [`unescape_in()`], but it also returns a
+/// [`Diagnostic`] for every reference that has a [parse error][errors] (plus
+/// ambiguous ampersands). They are in the order they appear in the input.
+///
+/// ```rust
+/// use htmlize::{unescape_with_diagnostics, Context, ParseErrorKind};
+/// # use assert2::assert;
+///
+/// let (text, diagnostics) =
+///     unescape_with_diagnostics("&copy 2024 &foo; &#0;", Context::General);
+/// assert!(text == "© 2024 &foo; \u{fffd}");
+///
+/// let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind).collect();
+/// assert!(
+///     kinds
+///         == [
+///             ParseErrorKind::MissingSemicolonAfterCharacterReference,
+///             ParseErrorKind::UnknownNamedCharacterReference,
+///             ParseErrorKind::NullCharacterReference,
+///         ]
+/// );
+/// assert!(diagnostics[1].range == (11..16));
+/// assert!(diagnostics[1].text == "&foo;");
+/// ```
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+///
+/// [`unescape_in()`]: super::unescape_in
+/// [errors]: https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+pub fn unescape_with_diagnostics<'a, S: Into<Cow<'a, str>>>(
+    escaped: S,
+    context: Context,
+) -> (Cow<'a, str>, Vec<Diagnostic>) {
+    let escaped = escaped.into();
+    let mut diagnostics = Vec::new();
+    let result = unescape_diagnose_in(&escaped, context, |diagnostic| {
+        diagnostics.push(diagnostic);
+        Ok::<_, Infallible>(())
+    });
+
+    let unescaped = match result {
+        Ok(Some(buffer)) => String::from_utf8(buffer).unwrap().into(),
+        Ok(None) => escaped,
+        Err(never) => match never {},
+    };
+    (unescaped, diagnostics)
+}
+
+/// An error returned by [`try_unescape_in()`].
+///
+/// This wraps the [`Diagnostic`] for the first malformed reference.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct UnescapeError(Diagnostic);
+
+impl UnescapeError {
+    /// What’s wrong with the reference.
+    #[must_use]
+    pub const fn kind(&self) -> ParseErrorKind {
+        self.0.kind
+    }
+
+    /// The byte range of the reference in the input.
+    #[must_use]
+    pub fn range(&self) -> Range<usize> {
+        self.0.range.clone()
+    }
+
+    /// The text of the reference, e.g. `"&amp"`.
+    #[must_use]
+    pub fn text(&self) -> &str {
+        &self.0.text
+    }
+
+    /// The [`Diagnostic`] for the reference.
+    #[must_use]
+    pub const fn diagnostic(&self) -> &Diagnostic {
+        &self.0
+    }
+
+    /// Convert this into the [`Diagnostic`] for the reference.
+    #[must_use]
+    pub fn into_diagnostic(self) -> Diagnostic {
+        self.0
+    }
+}
+
+impl From<Diagnostic> for UnescapeError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        Self(diagnostic)
+    }
+}
+
+impl fmt::Display for UnescapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid character reference {:?} at bytes {}..{}: {}",
+            self.0.text, self.0.range.start, self.0.range.end, self.0.kind
+        )
+    }
+}
+
+impl std::error::Error for UnescapeError {}
+
+/// Expand all entities in a given context, or fail on the first malformed
+/// reference.
+///
+/// Where [`unescape_in()`] recovers from [parse errors][errors] the same way a
+/// browser would, this returns an [`UnescapeError`] for the first one found.
+/// This is useful for validating input that is supposed to be well-formed,
+/// e.g. machine generated HTML.
+///
+/// ```rust
+/// use htmlize::{try_unescape_in, Context, ParseErrorKind};
+/// # use assert2::assert;
+///
+/// assert!(try_unescape_in("3 &times; 4 &gt; 10", Context::General).unwrap() == "3 × 4 > 10");
+///
+/// let error = try_unescape_in("a &amp b &#0;", Context::General).unwrap_err();
+/// assert!(error.kind() == ParseErrorKind::MissingSemicolonAfterCharacterReference);
+/// assert!(error.range() == (2..6));
+///
+/// let error = try_unescape_in("&foo;", Context::General).unwrap_err();
+/// assert!(error.kind() == ParseErrorKind::UnknownNamedCharacterReference);
+/// ```
+///
+/// A `&` that isn’t followed by something that looks like a reference is not
+/// an error, so `"a & b"` is accepted. Neither is
+/// [`ParseErrorKind::AmbiguousAmpersand`] since it isn’t a parse error in the
+/// spec.
+///
+/// # Errors
+///
+/// Returns an error for the first reference that is a parse error according
+/// to the WHATWG spec, e.g. `&#0;`, `&#xD800;`, `&amp` (missing semicolon), or
+/// `&foo;` (unknown entity).
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+///
+/// [`unescape_in()`]: super::unescape_in
+/// [errors]: https://html.spec.whatwg.org/multipage/parsing.html#parse-errors
+pub fn try_unescape_in<'a, S: Into<Cow<'a, str>>>(
+    escaped: S,
+    context: Context,
+) -> Result<Cow<'a, str>, UnescapeError> {
+    let escaped = escaped.into();
+    let result = unescape_diagnose_in(&escaped, context, |diagnostic| {
+        if diagnostic.kind == ParseErrorKind::AmbiguousAmpersand {
+            Ok(())
+        } else {
+            Err(UnescapeError::from(diagnostic))
+        }
+    });
+
+    Ok(match result? {
+        Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+        None => escaped,
+    })
+}
+
+/// Unescape `escaped` in `context`, passing parse errors to `report`.
+///
+/// Stops and returns the error if `report` returns an error.
+fn unescape_diagnose_in<E, F>(
+    escaped: &str,
+    context: Context,
+    report: F,
+) -> Result<Option<Vec<u8>>, E>
+where
+    F: FnMut(Diagnostic) -> Result<(), E>,
+{
+    match context {
+        Context::General => unescape_diagnose::<
+            (DefaultMatcher, internal::ContextGeneral),
+            E,
+            F,
+        >(escaped.as_bytes(), report),
+        Context::Attribute => unescape_diagnose::<
+            (DefaultMatcher, internal::ContextAttribute),
+            E,
+            F,
+        >(escaped.as_bytes(), report),
+    }
+}
+
+/// Unescape `escaped` and pass any parse errors to `report`.
+///
+/// Returns `Ok(None)` if no changes would be made, or the first error returned
+/// by `report`.
+fn unescape_diagnose<M, E, F>(
+    escaped: &[u8],
+    mut report: F,
+) -> Result<Option<Vec<u8>>, E>
+where
+    M: internal::Matcher,
+    F: FnMut(Diagnostic) -> Result<(), E>,
+{
+    internal::try_replace_references(escaped, |start| {
+        let rest = &escaped[start..];
+        if let Some((len, expansion)) = internal::match_entity_len::<M>(rest) {
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "len <= escaped.len() - start"
+            )]
+            let end = start + len;
+            let reference = &escaped[start..end];
+            if reference.last() != Some(&b';') {
+                report(diagnostic(
+                    escaped,
+                    ParseErrorKind::MissingSemicolonAfterCharacterReference,
+                    start..end,
+                ))?;
+            }
+            if let Some(kind) = numeric_reference_error(reference) {
+                report(diagnostic(escaped, kind, start..end))?;
+            }
+            Ok(Some((len, expansion)))
+        } else {
+            if let Some((kind, len)) = unexpanded_error(rest) {
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "len <= escaped.len() - start"
+                )]
+                report(diagnostic(escaped, kind, start..start + len))?;
+            }
+            Ok(None)
+        }
+    })
+}
+
+/// Create a [`Diagnostic`] for `escaped[range]`.
+fn diagnostic(
+    escaped: &[u8],
+    kind: ParseErrorKind,
+    range: Range<usize>,
+) -> Diagnostic {
+    Diagnostic {
+        kind,
+        text: String::from_utf8_lossy(&escaped[range.clone()]).into_owned(),
+        range,
+    }
+}
+
+/// Check the value of an expanded numeric reference like `&#x80;`.
+///
+/// Returns `None` if `reference` isn’t numeric or if the value is fine.
+fn numeric_reference_error(reference: &[u8]) -> Option<ParseErrorKind> {
+    let (digits, radix) = match reference {
+        [b'&', b'#', b'x' | b'X', digits @ ..] => (digits, 16),
+        [b'&', b'#', digits @ ..] => (digits, 10),
+        _ => return None,
+    };
+
+    let number = digits
+        .iter()
+        .map_while(|&c| char::from(c).to_digit(radix))
+        .fold(0_u32, |n, digit| {
+            n.saturating_mul(radix).saturating_add(digit)
+        });
+    code_point_error(number)
+}
+
+/// Check whether a numeric reference to `number` is a parse error.
+///
+/// See <https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state>
+const fn code_point_error(number: u32) -> Option<ParseErrorKind> {
+    match number {
+        0x00 => Some(ParseErrorKind::NullCharacterReference),
+        0x11_0000.. => {
+            Some(ParseErrorKind::CharacterReferenceOutsideUnicodeRange)
+        }
+        0xD800..=0xDFFF => Some(ParseErrorKind::SurrogateCharacterReference),
+        0xFDD0..=0xFDEF => Some(ParseErrorKind::NoncharacterCharacterReference),
+        n if n & 0xFFFE == 0xFFFE => {
+            Some(ParseErrorKind::NoncharacterCharacterReference)
+        }
+        // ASCII whitespace other than 0x0D is allowed.
+        0x09 | 0x0A | 0x0C | 0x20 => None,
+        0x01..=0x1F | 0x7F..=0x9F => {
+            Some(ParseErrorKind::ControlCharacterReference)
+        }
+        _ => None,
+    }
+}
+
+/// Check a reference at the start of `rest` that was not expanded.
+///
+/// Returns the error and the length of the reference, or `None` if this is
+/// just an `&` that isn’t followed by anything that looks like a reference.
+fn unexpanded_error(rest: &[u8]) -> Option<(ParseErrorKind, usize)> {
+    match rest {
+        [b'&', b'#', b'x' | b'X', ..] => Some((
+            ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference,
+            3,
+        )),
+        [b'&', b'#', ..] => Some((
+            ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference,
+            2,
+        )),
+        [b'&', name @ ..] => {
+            let len = name
+                .iter()
+                .position(|c| !c.is_ascii_alphanumeric())
+                .unwrap_or(name.len());
+            match name.get(len) {
+                _ if len == 0 => None,
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "len < rest.len()"
+                )]
+                Some(b';') => Some((
+                    ParseErrorKind::UnknownNamedCharacterReference,
+                    len + 2,
+                )),
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "len < rest.len()"
+                )]
+                _ => Some((ParseErrorKind::AmbiguousAmpersand, len + 1)),
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    /// Get the kinds and text of all diagnostics for `input`.
+    fn diagnose(
+        input: &str,
+        context: Context,
+    ) -> Vec<(ParseErrorKind, String)> {
+        let (unescaped, diagnostics) =
+            unescape_with_diagnostics(input, context);
+        assert!(unescaped == crate::unescape_in(input, context));
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                assert!(input[diagnostic.range] == diagnostic.text);
+                (diagnostic.kind, diagnostic.text)
+            })
+            .collect()
+    }
+
+    macro_rules! test {
+        ($name:ident, $input:expr, $context:ident, [$(($kind:ident, $text:expr)),* $(,)?]) => {
+            #[test]
+            fn $name() {
+                assert!(
+                    diagnose($input, Context::$context)
+                        == [$((ParseErrorKind::$kind, String::from($text))),*]
+                );
+            }
+        };
+    }
+
+    test!(none, "a &amp; b &#x20; &#32; & c", General, []);
+    test!(empty, "", General, []);
+    test!(
+        missing_semicolon,
+        "&amp &#38 &#x26 &timesbar",
+        General,
+        [
+            (MissingSemicolonAfterCharacterReference, "&amp"),
+            (MissingSemicolonAfterCharacterReference, "&#38"),
+            (MissingSemicolonAfterCharacterReference, "&#x26"),
+            (MissingSemicolonAfterCharacterReference, "&times"),
+        ]
+    );
+    test!(
+        unknown,
+        "&foo; &time;",
+        General,
+        [
+            (UnknownNamedCharacterReference, "&foo;"),
+            (UnknownNamedCharacterReference, "&time;"),
+        ]
+    );
+    test!(
+        ambiguous,
+        "&foo &time",
+        General,
+        [(AmbiguousAmpersand, "&foo"), (AmbiguousAmpersand, "&time")]
+    );
+    test!(
+        attribute_ambiguous,
+        "&timesx &times= &times",
+        Attribute,
+        [
+            (AmbiguousAmpersand, "&timesx"),
+            (AmbiguousAmpersand, "&times"),
+            (MissingSemicolonAfterCharacterReference, "&times"),
+        ]
+    );
+    test!(
+        no_digits,
+        "&#; &#x; &#xZ &#",
+        General,
+        [
+            (AbsenceOfDigitsInNumericCharacterReference, "&#"),
+            (AbsenceOfDigitsInNumericCharacterReference, "&#x"),
+            (AbsenceOfDigitsInNumericCharacterReference, "&#x"),
+            (AbsenceOfDigitsInNumericCharacterReference, "&#"),
+        ]
+    );
+    test!(null, "&#0;", General, [(NullCharacterReference, "&#0;")]);
+    test!(
+        null_missing_semicolon,
+        "&#x0",
+        General,
+        [
+            (MissingSemicolonAfterCharacterReference, "&#x0"),
+            (NullCharacterReference, "&#x0"),
+        ]
+    );
+    test!(
+        outside_range,
+        "&#x110000; &#x110000000000000000;",
+        General,
+        [
+            (CharacterReferenceOutsideUnicodeRange, "&#x110000;"),
+            (
+                CharacterReferenceOutsideUnicodeRange,
+                "&#x110000000000000000;"
+            ),
+        ]
+    );
+    test!(
+        surrogate,
+        "&#xD800; &#57343;",
+        General,
+        [
+            (SurrogateCharacterReference, "&#xD800;"),
+            (SurrogateCharacterReference, "&#57343;"),
+        ]
+    );
+    test!(
+        noncharacter,
+        "&#xFDD0; &#xFFFE; &#x10FFFF; &#xFFFD;",
+        General,
+        [
+            (NoncharacterCharacterReference, "&#xFDD0;"),
+            (NoncharacterCharacterReference, "&#xFFFE;"),
+            (NoncharacterCharacterReference, "&#x10FFFF;"),
+        ]
+    );
+    test!(
+        control,
+        "&#x1; &#13; &#x7F; &#x80; &#9; &#10; &#12; &#32;",
+        General,
+        [
+            (ControlCharacterReference, "&#x1;"),
+            (ControlCharacterReference, "&#13;"),
+            (ControlCharacterReference, "&#x7F;"),
+            (ControlCharacterReference, "&#x80;"),
+        ]
+    );
+
+    #[test]
+    fn range() {
+        let (_, diagnostics) =
+            unescape_with_diagnostics("é &foo;", Context::General);
+        assert!(diagnostics.len() == 1);
+        assert!(diagnostics[0].range == (3..8));
+        assert!(
+            diagnostics[0].to_string()
+                == "unknown-named-character-reference at bytes 3..8: \"&foo;\""
+        );
+    }
+
+    #[cfg(feature = "entities")]
+    #[test]
+    fn suggestions() {
+        let (_, diagnostics) = unescape_with_diagnostics(
+            "&amp &#38 &ampp; &lt=x &#0; &zzzz;",
+            Context::General,
+        );
+        let suggestions: Vec<_> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.suggestions().first().copied())
+            .collect();
+        assert!(
+            suggestions
+                == [
+                    Some("&amp;"),
+                    None,
+                    Some("&amp;"),
+                    Some("&lt;"),
+                    None,
+                    None,
+                ]
+        );
+    }
+
+    #[test]
+    fn try_unescape_ok() {
+        assert!(
+            try_unescape_in(
+                "&lt;a href=&quot;x&quot;&gt; & &#x1F600;",
+                Context::General
+            ) == Ok(Cow::Owned(String::from("<a href=\"x\"> & 😀")))
+        );
+    }
+
+    #[test]
+    fn try_unescape_borrowed() {
+        assert!(let Ok(Cow::Borrowed(_)) = try_unescape_in("a & b", Context::General));
+    }
+
+    #[test]
+    fn try_unescape_ambiguous_ok() {
+        assert!(
+            try_unescape_in("&foo &timesx=&times;", Context::Attribute)
+                .unwrap()
+                == "&foo &timesx=×"
+        );
+    }
+
+    #[test]
+    fn try_unescape_first_error() {
+        let error =
+            try_unescape_in("ok &amp; &#xD800; &foo;", Context::General)
+                .unwrap_err();
+        assert!(error.kind() == ParseErrorKind::SurrogateCharacterReference);
+        assert!(error.range() == (9..17));
+        assert!(error.text() == "&#xD800;");
+        assert!(error.diagnostic().text == "&#xD800;");
+        assert!(
+            error.to_string()
+                == "invalid character reference \"&#xD800;\" at bytes 9..17: \
+                    surrogate-character-reference"
+        );
+    }
+
+    #[test]
+    fn try_unescape_no_digits() {
+        let error = try_unescape_in("&#;", Context::General).unwrap_err();
+        assert!(
+            error.kind()
+                == ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference
+        );
+    }
+}
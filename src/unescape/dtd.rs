@@ -0,0 +1,1130 @@
+//! Load named entities from `<!ENTITY>` declarations in a DTD.
+
+use super::table::{check_name, is_name_byte, EntityTable};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+/// Limits on entity expansion for
+/// [`EntityTable::load_dtd_with_limits()`].
+///
+/// Entities can refer to other entities, so a tiny DTD can define an entity
+/// that expands to gigabytes of text (the [“billion laughs” attack][laughs]).
+/// Loading stops with an error if any limit is exceeded.
+///
+/// ```rust
+/// use htmlize::{DtdLimits, EntityTable};
+///
+/// let limits = DtdLimits::new().max_depth(4).max_entity_len(1024);
+/// let mut table = EntityTable::empty();
+/// assert!(table
+///     .load_dtd_with_limits(r#"<!ENTITY a "&b;&b;"> <!ENTITY b "b">"#, limits)
+///     .is_ok());
+/// ```
+///
+/// [laughs]: https://en.wikipedia.org/wiki/Billion_laughs_attack
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DtdLimits {
+    /// How deeply entity references may be nested.
+    depth: usize,
+
+    /// The maximum length in bytes of a single entity.
+    entity_len: usize,
+
+    /// The maximum length in bytes of all entities together.
+    total_len: usize,
+}
+
+impl DtdLimits {
+    /// The default limits: references nested 16 deep, 64 KiB per entity, and
+    /// 16 MiB in total.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { depth: 16, entity_len: 65_536, total_len: 16_777_216 }
+    }
+
+    /// Set how deeply entity references may be nested, counting parameter
+    /// entity references and conditional sections between declarations.
+    #[must_use]
+    pub const fn max_depth(self, max_depth: usize) -> Self {
+        Self { depth: max_depth, ..self }
+    }
+
+    /// Set the maximum length in bytes of any single entity after expansion.
+    #[must_use]
+    pub const fn max_entity_len(self, max_entity_len: usize) -> Self {
+        Self { entity_len: max_entity_len, ..self }
+    }
+
+    /// Set the maximum length in bytes of all expanded entities together,
+    /// including the replacement text of every declared entity and parameter
+    /// entities that are expanded between declarations.
+    #[must_use]
+    pub const fn max_total_len(self, max_total_len: usize) -> Self {
+        Self { total_len: max_total_len, ..self }
+    }
+}
+
+impl Default for DtdLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A kind of error found by [`EntityTable::load_dtd()`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum DtdErrorKind {
+    /// The DTD could not be parsed, e.g. a declaration was missing its name.
+    Syntax,
+    /// An entity name can’t be used in an [`EntityTable`], e.g. it contains
+    /// non-ASCII characters.
+    InvalidName(String),
+    /// A reference to an entity that isn’t defined or can’t be loaded, e.g.
+    /// `&foo;` or `%foo;`.
+    UndefinedEntity(String),
+    /// An entity refers to itself, directly or indirectly.
+    RecursiveEntity(String),
+    /// A character reference to an invalid code point, e.g. `&#0;`.
+    InvalidCharacter,
+    /// References were nested more deeply than [`DtdLimits::max_depth()`].
+    DepthLimit,
+    /// An entity, or all entities together, expanded to more text than
+    /// [`DtdLimits`] allows.
+    SizeLimit,
+}
+
+impl fmt::Display for DtdErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax => f.write_str("syntax error"),
+            Self::InvalidName(name) => {
+                write!(f, "unsupported entity name {name:?}")
+            }
+            Self::UndefinedEntity(name) => {
+                write!(f, "undefined entity {name}")
+            }
+            Self::RecursiveEntity(name) => {
+                write!(f, "entity {name} refers to itself")
+            }
+            Self::InvalidCharacter => f.write_str("invalid character reference"),
+            Self::DepthLimit => f.write_str("entities nested too deeply"),
+            Self::SizeLimit => f.write_str("entities expand to too much text"),
+        }
+    }
+}
+
+/// An error returned by [`EntityTable::load_dtd()`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DtdError {
+    /// What went wrong.
+    pub kind: DtdErrorKind,
+    /// The byte offset in the DTD where the error was found.
+    ///
+    /// Errors in the text of an entity are reported at its declaration.
+    /// Errors in a parameter entity that is expanded between declarations,
+    /// e.g. `%ISOnum;`, are reported at the reference.
+    pub offset: usize,
+}
+
+impl fmt::Display for DtdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid DTD at byte {}: {}", self.offset, self.kind)
+    }
+}
+
+impl std::error::Error for DtdError {}
+
+/// Parse the declarations in `dtd`, and return the fully expanded general
+/// entities as `vec![...("amp", "&")...]`.
+///
+/// References to entities not declared in `dtd` are looked up in `table`.
+pub(super) fn parse_dtd(
+    table: &EntityTable,
+    dtd: &str,
+    limits: DtdLimits,
+) -> Result<Vec<(String, String)>, DtdError> {
+    let mut parser = DtdParser {
+        table,
+        limits,
+        general: BTreeMap::new(),
+        parameters: HashMap::new(),
+        expanded: HashMap::new(),
+        stack: Vec::new(),
+        total_len: 0,
+    };
+    parser.declarations(&mut Cursor::new(dtd), 0, None, false)?;
+    parser.expand_all()
+}
+
+/// The value of a declared entity.
+#[derive(Clone, Debug)]
+enum Entity {
+    /// An internal entity with its replacement text.
+    Internal(String),
+    /// An SGML `CDATA` entity, which is used as is.
+    Cdata(String),
+    /// An external entity, which can’t be loaded.
+    External,
+}
+
+/// State for loading a DTD.
+struct DtdParser<'t> {
+    /// The table being loaded into, for references to existing entities.
+    table: &'t EntityTable,
+
+    /// Limits on expansion.
+    limits: DtdLimits,
+
+    /// General entities with the offsets of their declarations.
+    general: BTreeMap<String, (Entity, usize)>,
+
+    /// Parameter entities.
+    parameters: HashMap<String, Entity>,
+
+    /// General entities that have been fully expanded, with their heights
+    /// (how deeply references are nested in them).
+    expanded: HashMap<String, (String, usize)>,
+
+    /// General entities that are currently being expanded.
+    stack: Vec<String>,
+
+    /// The total length of everything expanded so far.
+    total_len: usize,
+}
+
+impl DtdParser<'_> {
+    /// Parse declarations until the end of the input, or until `]]>` if
+    /// `in_section` is true.
+    ///
+    /// If `origin` is set, errors are reported at that offset instead of the
+    /// offset in `cursor`.
+    fn declarations(
+        &mut self,
+        cursor: &mut Cursor<'_>,
+        depth: usize,
+        origin: Option<usize>,
+        in_section: bool,
+    ) -> Result<(), DtdError> {
+        loop {
+            cursor.skip_whitespace();
+            let offset = origin.unwrap_or_else(|| cursor.offset());
+            let syntax = || error(DtdErrorKind::Syntax, offset);
+
+            if cursor.is_empty() {
+                return if in_section { Err(syntax()) } else { Ok(()) };
+            } else if in_section && cursor.eat("]]>") {
+                return Ok(());
+            } else if cursor.eat("<!--") {
+                if !cursor.skip_past("-->") {
+                    return Err(syntax());
+                }
+            } else if cursor.eat("<?") {
+                if !cursor.skip_past("?>") {
+                    return Err(syntax());
+                }
+            } else if cursor.eat("<![") {
+                self.conditional_section(cursor, depth, origin, offset)?;
+            } else if cursor.eat("<!ENTITY") {
+                self.entity_declaration(cursor, offset)?;
+            } else if cursor.eat("<!") {
+                if !cursor.skip_markup() {
+                    return Err(syntax());
+                }
+            } else if cursor.eat("%") {
+                let text = self.parameter_reference(cursor, offset)?;
+                if let Some(text) = text {
+                    let depth = self.deeper(depth, offset)?;
+                    self.add_total_len(text.len(), offset)?;
+                    let mut inner = Cursor::new(&text);
+                    self.declarations(&mut inner, depth, Some(offset), false)?;
+                }
+            } else {
+                return Err(syntax());
+            }
+        }
+    }
+
+    /// Parse a conditional section after the `<![`.
+    fn conditional_section(
+        &mut self,
+        cursor: &mut Cursor<'_>,
+        depth: usize,
+        origin: Option<usize>,
+        offset: usize,
+    ) -> Result<(), DtdError> {
+        cursor.skip_whitespace();
+        let keyword = if cursor.eat("%") {
+            self.parameter_reference(cursor, offset)?
+                .ok_or_else(|| {
+                    error(DtdErrorKind::UndefinedEntity("%".to_owned()), offset)
+                })?
+                .trim()
+                .to_owned()
+        } else {
+            cursor.name().to_owned()
+        };
+        cursor.skip_whitespace();
+        if !cursor.eat("[") {
+            return Err(error(DtdErrorKind::Syntax, offset));
+        }
+
+        let depth = self.deeper(depth, offset)?;
+        match keyword.as_str() {
+            "INCLUDE" => self.declarations(cursor, depth, origin, true),
+            "IGNORE" if cursor.skip_ignored() => Ok(()),
+            _ => Err(error(DtdErrorKind::Syntax, offset)),
+        }
+    }
+
+    /// Parse a parameter entity reference after the `%`, and return its
+    /// replacement text.
+    ///
+    /// Returns `None` if it’s an external entity, which can’t be loaded.
+    fn parameter_reference(
+        &self,
+        cursor: &mut Cursor<'_>,
+        offset: usize,
+    ) -> Result<Option<String>, DtdError> {
+        let name = cursor.name();
+        if name.is_empty() || !cursor.eat(";") {
+            return Err(error(DtdErrorKind::Syntax, offset));
+        }
+
+        match self.parameters.get(name) {
+            Some(Entity::Internal(text) | Entity::Cdata(text)) => {
+                Ok(Some(text.clone()))
+            }
+            Some(Entity::External) => Ok(None),
+            None => Err(error(
+                DtdErrorKind::UndefinedEntity(format!("%{name};")),
+                offset,
+            )),
+        }
+    }
+
+    /// Parse an entity declaration after the `<!ENTITY`.
+    fn entity_declaration(
+        &mut self,
+        cursor: &mut Cursor<'_>,
+        offset: usize,
+    ) -> Result<(), DtdError> {
+        let syntax = || error(DtdErrorKind::Syntax, offset);
+
+        if !cursor.skip_whitespace() {
+            return Err(syntax());
+        }
+        let parameter = cursor.eat("%");
+        if parameter && !cursor.skip_whitespace() {
+            return Err(syntax());
+        }
+        if cursor.eat("#DEFAULT") {
+            // SGML default entity. There’s nothing to add to the table.
+            return if cursor.skip_markup() { Ok(()) } else { Err(syntax()) };
+        }
+
+        let name = cursor.name();
+        if name.is_empty() || !cursor.skip_whitespace() {
+            return Err(syntax());
+        }
+
+        let entity = if let Some(value) = cursor.quoted() {
+            Some(Entity::Internal(self.replacement_text(value, offset)?))
+        } else {
+            match cursor.name() {
+                "SYSTEM" | "PUBLIC" => Some(Entity::External),
+                "CDATA" => {
+                    cursor.skip_whitespace();
+                    let value = cursor.quoted().ok_or_else(syntax)?;
+                    Some(Entity::Cdata(value.to_owned()))
+                }
+                "SDATA" | "PI" | "STARTTAG" | "ENDTAG" | "MS" | "MD" => None,
+                _ => return Err(syntax()),
+            }
+        };
+
+        // Skip the rest, e.g. an external ID or an SGML comment.
+        if !cursor.skip_markup() {
+            return Err(syntax());
+        }
+
+        if let Some(Entity::Internal(text) | Entity::Cdata(text)) = &entity {
+            self.add_total_len(text.len(), offset)?;
+        }
+
+        if let Some(entity) = entity {
+            if parameter {
+                self.parameters.entry(name.to_owned()).or_insert(entity);
+            } else {
+                check_name(&format!("&{name};")).map_err(|_| {
+                    error(DtdErrorKind::InvalidName(name.to_owned()), offset)
+                })?;
+                self.general
+                    .entry(name.to_owned())
+                    .or_insert((entity, offset));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expand character references and parameter entity references in the
+    /// literal value of an entity declaration.
+    ///
+    /// General entity references are left alone.
+    fn replacement_text(
+        &self,
+        value: &str,
+        offset: usize,
+    ) -> Result<String, DtdError> {
+        let mut output = String::new();
+        let mut rest = value;
+        while let Some(i) = rest.find(['%', '&']) {
+            output.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            if let Some(after) = rest.strip_prefix("&#") {
+                let (c, after) = char_reference(after)
+                    .map_err(|kind| error(kind, offset))?;
+                output.push(c);
+                rest = after;
+            } else if let Some((name, after)) =
+                rest.strip_prefix('%').and_then(reference)
+            {
+                match self.parameters.get(name) {
+                    Some(Entity::Internal(text) | Entity::Cdata(text)) => {
+                        output.push_str(text);
+                    }
+                    Some(Entity::External) | None => {
+                        return Err(error(
+                            DtdErrorKind::UndefinedEntity(format!("%{name};")),
+                            offset,
+                        ));
+                    }
+                }
+                rest = after;
+            } else {
+                // A general entity reference, which is expanded when it’s
+                // used, or a lone `%`.
+                output.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+
+            self.check_entity_len(&output, offset)?;
+        }
+
+        output.push_str(rest);
+        self.check_entity_len(&output, offset)?;
+        Ok(output)
+    }
+
+    /// Fully expand all general entities that can be added to the table.
+    fn expand_all(&mut self) -> Result<Vec<(String, String)>, DtdError> {
+        #[allow(
+            clippy::needless_collect,
+            reason = "expand_general() needs to borrow self mutably"
+        )]
+        let names: Vec<String> = self
+            .general
+            .iter()
+            .filter(|(_, (entity, _))| !matches!(entity, Entity::External))
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        names
+            .into_iter()
+            .map(|name| {
+                let (expansion, _) = self.expand_general(&name)?;
+                Ok((name, expansion))
+            })
+            .collect()
+    }
+
+    /// Fully expand a declared internal general entity.
+    ///
+    /// Returns the expansion and its height, i.e. how deeply references are
+    /// nested in it, counting itself.
+    fn expand_general(
+        &mut self,
+        name: &str,
+    ) -> Result<(String, usize), DtdError> {
+        if let Some(expanded) = self.expanded.get(name) {
+            return Ok(expanded.clone());
+        }
+
+        let (entity, offset) = self.general[name].clone();
+        let (expansion, height) = match entity {
+            Entity::Internal(text) => {
+                if self.stack.iter().any(|other| other == name) {
+                    return Err(error(
+                        DtdErrorKind::RecursiveEntity(format!("&{name};")),
+                        offset,
+                    ));
+                }
+                if self.stack.len() >= self.limits.depth {
+                    return Err(error(DtdErrorKind::DepthLimit, offset));
+                }
+
+                self.stack.push(name.to_owned());
+                let result = self.expand_text(&text, offset);
+                self.stack.pop();
+                let (expansion, height) = result?;
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "height <= max_depth, which is a usize"
+                )]
+                let height = height + 1;
+                (expansion, height)
+            }
+            Entity::Cdata(text) => (text, 1),
+            Entity::External => unreachable!("external entities are skipped"),
+        };
+
+        if height > self.limits.depth {
+            return Err(error(DtdErrorKind::DepthLimit, offset));
+        }
+        self.add_total_len(expansion.len(), offset)?;
+        self.expanded
+            .insert(name.to_owned(), (expansion.clone(), height));
+        Ok((expansion, height))
+    }
+
+    /// Expand character references and general entity references in the
+    /// replacement text of an entity declared at `offset`.
+    ///
+    /// Returns the expansion and the maximum height of the entities it
+    /// references.
+    fn expand_text(
+        &mut self,
+        text: &str,
+        offset: usize,
+    ) -> Result<(String, usize), DtdError> {
+        let mut output = String::new();
+        let mut height = 0;
+        let mut rest = text;
+        while let Some(i) = rest.find('&') {
+            output.push_str(&rest[..i]);
+            rest = &rest[i..];
+
+            if let Some(after) = rest.strip_prefix("&#") {
+                let (c, after) = char_reference(after)
+                    .map_err(|kind| error(kind, offset))?;
+                output.push(c);
+                rest = after;
+            } else if let Some((name, after)) = reference(&rest[1..]) {
+                let (expansion, child_height) =
+                    self.expand_reference(name, offset)?;
+                output.push_str(&expansion);
+                height = height.max(child_height);
+                rest = after;
+            } else {
+                return Err(error(DtdErrorKind::Syntax, offset));
+            }
+
+            self.check_entity_len(&output, offset)?;
+        }
+
+        output.push_str(rest);
+        self.check_entity_len(&output, offset)?;
+        Ok((output, height))
+    }
+
+    /// Expand a reference to `&name;` in the text of an entity declared at
+    /// `offset`.
+    ///
+    /// Entities declared in the DTD come first, then entities already in the
+    /// table, then the predefined XML entities.
+    fn expand_reference(
+        &mut self,
+        name: &str,
+        offset: usize,
+    ) -> Result<(String, usize), DtdError> {
+        let undefined = || {
+            error(DtdErrorKind::UndefinedEntity(format!("&{name};")), offset)
+        };
+
+        match self.general.get(name) {
+            Some((Entity::External, _)) => return Err(undefined()),
+            Some(_) => return self.expand_general(name),
+            None => {}
+        }
+
+        if let Some(expansion) = self.table.get(&format!("&{name};")) {
+            return Ok((expansion.to_owned(), 0));
+        }
+
+        let expansion = match name {
+            "amp" => "&",
+            "lt" => "<",
+            "gt" => ">",
+            "apos" => "'",
+            "quot" => "\"",
+            _ => return Err(undefined()),
+        };
+        Ok((expansion.to_owned(), 0))
+    }
+
+    /// Get the depth inside one more parameter entity or conditional
+    /// section, or return an error if that would exceed the limit.
+    const fn deeper(&self, depth: usize, offset: usize) -> Result<usize, DtdError> {
+        if depth >= self.limits.depth {
+            Err(error(DtdErrorKind::DepthLimit, offset))
+        } else {
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "depth < max_depth"
+            )]
+            let depth = depth + 1;
+            Ok(depth)
+        }
+    }
+
+    /// Check that an entity isn’t longer than the limit.
+    const fn check_entity_len(
+        &self,
+        text: &str,
+        offset: usize,
+    ) -> Result<(), DtdError> {
+        if text.len() > self.limits.entity_len {
+            Err(error(DtdErrorKind::SizeLimit, offset))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Add to the total length of expanded text, or return an error if it
+    /// exceeds the limit.
+    fn add_total_len(
+        &mut self,
+        len: usize,
+        offset: usize,
+    ) -> Result<(), DtdError> {
+        self.total_len = self.total_len.saturating_add(len);
+        if self.total_len > self.limits.total_len {
+            Err(error(DtdErrorKind::SizeLimit, offset))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Create a [`DtdError`].
+const fn error(kind: DtdErrorKind, offset: usize) -> DtdError {
+    DtdError { kind, offset }
+}
+
+/// Parse a name followed by `;` at the start of `input`.
+///
+/// Returns the name and the text after the `;`.
+fn reference(input: &str) -> Option<(&str, &str)> {
+    let len = input.bytes().take_while(|&c| is_name_byte(c)).count();
+    let rest = input[len..].strip_prefix(';')?;
+    if len == 0 {
+        None
+    } else {
+        Some((&input[..len], rest))
+    }
+}
+
+/// Parse a character reference after the `&#`.
+///
+/// Returns the character and the text after the `;`.
+fn char_reference(input: &str) -> Result<(char, &str), DtdErrorKind> {
+    let (digits, radix) = match input.strip_prefix('x') {
+        Some(hex) => (hex, 16),
+        None => (input, 10),
+    };
+    let len = digits.chars().take_while(|c| c.is_digit(radix)).count();
+    let rest = digits[len..].strip_prefix(';').ok_or(DtdErrorKind::Syntax)?;
+    if len == 0 {
+        return Err(DtdErrorKind::Syntax);
+    }
+
+    u32::from_str_radix(&digits[..len], radix)
+        .ok()
+        .and_then(char::from_u32)
+        .filter(|&c| c != '\0')
+        .map(|c| (c, rest))
+        .ok_or(DtdErrorKind::InvalidCharacter)
+}
+
+/// A position in DTD text.
+struct Cursor<'a> {
+    /// The full text.
+    text: &'a str,
+
+    /// The text that hasn’t been parsed yet.
+    rest: &'a str,
+}
+
+impl<'a> Cursor<'a> {
+    /// Create a cursor at the start of `text`.
+    const fn new(text: &'a str) -> Self {
+        Self { text, rest: text }
+    }
+
+    /// The offset of the next byte to be parsed.
+    const fn offset(&self) -> usize {
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "rest is a subslice at the end of text"
+        )]
+        let offset = self.text.len() - self.rest.len();
+        offset
+    }
+
+    /// Whether everything has been parsed.
+    const fn is_empty(&self) -> bool {
+        self.rest.is_empty()
+    }
+
+    /// Skip `len` bytes.
+    fn advance(&mut self, len: usize) {
+        self.rest = &self.rest[len..];
+    }
+
+    /// Skip `prefix` if it’s next. Returns whether it was found.
+    fn eat(&mut self, prefix: &str) -> bool {
+        if self.rest.starts_with(prefix) {
+            self.advance(prefix.len());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skip whitespace. Returns whether there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let len = self
+            .rest
+            .bytes()
+            .take_while(|c| matches!(c, b' ' | b'\t' | b'\n' | b'\r'))
+            .count();
+        self.advance(len);
+        len > 0
+    }
+
+    /// Skip past the next `needle`. Returns whether it was found.
+    fn skip_past(&mut self, needle: &str) -> bool {
+        if let Some(i) = self.rest.find(needle) {
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "needle is inside rest"
+            )]
+            let end = i + needle.len();
+            self.advance(end);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse a name.
+    ///
+    /// This includes any non-ASCII characters so that they can be reported
+    /// as an invalid name rather than a syntax error.
+    fn name(&mut self) -> &'a str {
+        let len = self
+            .rest
+            .bytes()
+            .take_while(|&c| is_name_byte(c) || !c.is_ascii())
+            .count();
+        let name = &self.rest[..len];
+        self.advance(len);
+        name
+    }
+
+    /// Parse a quoted string, and return its contents.
+    ///
+    /// Returns `None` if there isn’t one.
+    fn quoted(&mut self) -> Option<&'a str> {
+        let quote = self.rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+        let inside = &self.rest[1..];
+        let value = &inside[..inside.find(quote)?];
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "the quotes are inside rest"
+        )]
+        let end = value.len() + 2;
+        self.advance(end);
+        Some(value)
+    }
+
+    /// Skip past the `>` that ends a declaration. Quoted strings and SGML
+    /// comments (`-- ... --`) are skipped. Returns whether it was found.
+    fn skip_markup(&mut self) -> bool {
+        loop {
+            if self.eat(">") {
+                return true;
+            } else if self.eat("--") {
+                if !self.skip_past("--") {
+                    return false;
+                }
+            } else if self.quoted().is_none() {
+                match self.rest.chars().next() {
+                    Some(c) => self.advance(c.len_utf8()),
+                    None => return false,
+                }
+            }
+        }
+    }
+
+    /// Skip past the `]]>` that ends an ignored conditional section after
+    /// the `[`, including any nested sections. Returns whether it was found.
+    fn skip_ignored(&mut self) -> bool {
+        let mut level = 1_usize;
+        while level > 0 {
+            let close = match self.rest.find("]]>") {
+                Some(close) => close,
+                None => return false,
+            };
+            match self.rest.find("<![") {
+                Some(open) if open < close => {
+                    self.skip_past("<![");
+                    level = level.saturating_add(1);
+                }
+                _ => {
+                    self.skip_past("]]>");
+                    level = level.saturating_sub(1);
+                }
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Context;
+    use assert2::{assert, let_assert};
+
+    /// Load `dtd` into an empty table.
+    fn load(dtd: &str) -> Result<EntityTable, DtdError> {
+        let mut table = EntityTable::empty();
+        table.load_dtd(dtd).map(|()| table)
+    }
+
+    /// Load `dtd` and return the error.
+    fn load_error(dtd: &str) -> DtdError {
+        let_assert!(Err(error) = load(dtd));
+        error
+    }
+
+    #[test]
+    fn simple() {
+        let table =
+            load(r#"<!ENTITY a "A"><!ENTITY b 'B "b"'> <!ENTITY c "">"#)
+                .unwrap();
+        assert!(table.len() == 3);
+        assert!(table.get("&a;") == Some("A"));
+        assert!(table.get("&b;") == Some("B \"b\""));
+        assert!(table.get("&c;") == Some(""));
+        assert!(table.get("&a") == None);
+    }
+
+    #[test]
+    fn character_references() {
+        let table = load(
+            r#"<!ENTITY amp "&#38;#38;"> <!ENTITY lt "&#38;#60;">
+            <!ENTITY x "&#x1F600;&#65;">"#,
+        )
+        .unwrap();
+        assert!(table.get("&amp;") == Some("&"));
+        assert!(table.get("&lt;") == Some("<"));
+        assert!(table.get("&x;") == Some("😀A"));
+    }
+
+    #[test]
+    fn general_references() {
+        let mut table = EntityTable::empty();
+        table.insert("&existing;", "E").unwrap();
+        table
+            .load_dtd(
+                r#"<!ENTITY a "[&b;|&c;]"> <!ENTITY b "&c;&c;">
+                <!ENTITY c "c&existing;&amp;&apos;">"#,
+            )
+            .unwrap();
+        assert!(table.get("&a;") == Some("[cE&'cE&'|cE&']"));
+    }
+
+    #[test]
+    fn first_declaration_wins() {
+        let table = load(r#"<!ENTITY a "1"> <!ENTITY a "2">"#).unwrap();
+        assert!(table.get("&a;") == Some("1"));
+    }
+
+    #[test]
+    fn replaces_existing() {
+        let mut table = EntityTable::new();
+        table.load_dtd(r#"<!ENTITY copy "(c)">"#).unwrap();
+        assert!(
+            table.unescape_in("&copy; &copy", Context::General) == "(c) ©"
+        );
+    }
+
+    #[test]
+    fn parameter_entities() {
+        let table = load(
+            r#"<!ENTITY % v "value">
+            <!ENTITY % decls "<!ENTITY from-pe '%v;'>">
+            <!ENTITY a "%v; %v;">
+            %decls;"#,
+        )
+        .unwrap();
+        assert!(table.get("&a;") == Some("value value"));
+        assert!(table.get("&from-pe;") == Some("value"));
+        assert!(table.get("&v;") == None);
+    }
+
+    #[test]
+    fn external_entities_skipped() {
+        let table = load(
+            r#"<!ENTITY % ISOnum PUBLIC
+                "ISO 8879:1986//ENTITIES Numeric and Special Graphic//EN"
+                "isonum.ent">
+            %ISOnum;
+            <!ENTITY logo SYSTEM "logo.gif" NDATA gif>
+            <!ENTITY a "A">"#,
+        )
+        .unwrap();
+        assert!(table.len() == 1);
+    }
+
+    #[test]
+    fn skips_other_markup() {
+        let table = load(
+            r#"<?xml version="1.0"?>
+            <!-- <!ENTITY no "comment"> -->
+            <!ELEMENT p (#PCDATA)>
+            <!ATTLIST p title CDATA "a > b">
+            <!NOTATION gif SYSTEM "image/gif">
+            <!ENTITY a "A">"#,
+        )
+        .unwrap();
+        assert!(table.len() == 1);
+        assert!(table.get("&a;") == Some("A"));
+    }
+
+    #[test]
+    fn sgml_entity_set() {
+        let table = load(
+            r#"<!-- Character entity set. Typical invocation:
+                 <!ENTITY % ISOnum PUBLIC
+                   "ISO 8879:1986//ENTITIES Numeric and Special Graphic//EN">
+                 %ISOnum;
+            -->
+            <!ENTITY half   SDATA "[half  ]"--=fraction one-half-->
+            <!ENTITY frac12 CDATA "&#189;" -- raw -->
+            <!ENTITY #DEFAULT SYSTEM>
+            <!ENTITY ohm "&#x2126;" --=ohm sign-->"#,
+        )
+        .unwrap();
+        assert!(table.len() == 2);
+        assert!(table.get("&frac12;") == Some("&#189;"));
+        assert!(table.get("&ohm;") == Some("\u{2126}"));
+    }
+
+    #[test]
+    fn conditional_sections() {
+        let table = load(
+            r#"<!ENTITY % yes "INCLUDE"> <!ENTITY % no "IGNORE">
+            <![%yes;[
+                <!ENTITY a "A">
+                <![ IGNORE [ <!ENTITY b "B"> <![INCLUDE[ ]]> ]]>
+            ]]>
+            <![%no;[ <!ENTITY c "C"> ]]>"#,
+        )
+        .unwrap();
+        assert!(table.len() == 1);
+        assert!(table.get("&a;") == Some("A"));
+    }
+
+    #[test]
+    fn xml_names() {
+        let table = load(r#"<!ENTITY b.alpha "&#x1D6C2;">"#).unwrap();
+        assert!(
+            table.unescape_in("&b.alpha;", Context::General) == "\u{1D6C2}"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            (r#"<!ENTITY a "A""#, DtdErrorKind::Syntax, 0),
+            (r#"<!ENTITY "A">"#, DtdErrorKind::Syntax, 0),
+            (r#"  <!ENTITY a BOGUS "A">"#, DtdErrorKind::Syntax, 2),
+            (r#"<!ENTITY a "&#0;">"#, DtdErrorKind::InvalidCharacter, 0),
+            (r#"<!ENTITY a "&#xD800;">"#, DtdErrorKind::InvalidCharacter, 0),
+            (r#"<!ENTITY a "&#12">"#, DtdErrorKind::Syntax, 0),
+            (r#"<!ENTITY a "a & b">"#, DtdErrorKind::Syntax, 0),
+            (r"<!-- ", DtdErrorKind::Syntax, 0),
+            (r"<![INCLUDE[ ", DtdErrorKind::Syntax, 12),
+            (r"<![IGNORE[ <![IGNORE[ ]]> ", DtdErrorKind::Syntax, 0),
+            (r"<![MAYBE[ ]]>", DtdErrorKind::Syntax, 0),
+            (r"junk", DtdErrorKind::Syntax, 0),
+            (
+                r#"<!ENTITY é "e">"#,
+                DtdErrorKind::InvalidName("é".to_owned()),
+                0,
+            ),
+            (
+                r#"<!ENTITY a "x"> <!ENTITY b "&nope;">"#,
+                DtdErrorKind::UndefinedEntity("&nope;".to_owned()),
+                16,
+            ),
+            (
+                r#"<!ENTITY b "%nope;">"#,
+                DtdErrorKind::UndefinedEntity("%nope;".to_owned()),
+                0,
+            ),
+            (
+                r" %nope;",
+                DtdErrorKind::UndefinedEntity("%nope;".to_owned()),
+                1,
+            ),
+            (
+                r#"<!ENTITY ext SYSTEM "x"> <!ENTITY a "&ext;">"#,
+                DtdErrorKind::UndefinedEntity("&ext;".to_owned()),
+                25,
+            ),
+            (
+                r#"<!ENTITY a "&b;"> <!ENTITY b "&a;">"#,
+                DtdErrorKind::RecursiveEntity("&a;".to_owned()),
+                0,
+            ),
+            (
+                r#"<!ENTITY a "&a;">"#,
+                DtdErrorKind::RecursiveEntity("&a;".to_owned()),
+                0,
+            ),
+        ];
+
+        for (dtd, kind, offset) in cases {
+            assert!(load_error(dtd) == DtdError { kind, offset }, "{dtd}");
+        }
+    }
+
+    #[test]
+    fn error_in_parameter_entity() {
+        let dtd = r#"<!ENTITY % p "<!ENTITY a 'A'> junk"> <!ENTITY b "B"> %p;"#;
+        assert!(
+            load_error(dtd)
+                == DtdError { kind: DtdErrorKind::Syntax, offset: 53 }
+        );
+    }
+
+    #[test]
+    fn error_adds_nothing() {
+        let mut table = EntityTable::empty();
+        assert!(table.load_dtd(r#"<!ENTITY a "A"> <!ENTITY b "&c;">"#).is_err());
+        assert!(table.is_empty());
+    }
+
+    /// Declare entities `{prefix}1` through `{prefix}9`, each of which refers
+    /// to the previous one ten times with `{sigil}{prefix}{i};`.
+    fn laughs(sigil: &str, prefix: &str) -> String {
+        use std::fmt::Write;
+
+        let declare = if sigil == "%" { "% " } else { "" };
+        let mut dtd = String::new();
+        for (previous, i) in (0..9).zip(1..10) {
+            let reference = format!("{sigil}{prefix}{previous};");
+            write!(
+                dtd,
+                r#"<!ENTITY {declare}{prefix}{i} "{}">"#,
+                reference.repeat(10)
+            )
+            .unwrap();
+        }
+        dtd
+    }
+
+    #[test]
+    fn billion_laughs() {
+        let dtd = format!(r#"<!ENTITY lol0 "lol">{}"#, laughs("&", "lol"));
+        let error = load_error(&dtd);
+        assert!(error.kind == DtdErrorKind::SizeLimit);
+
+        let mut table = EntityTable::empty();
+        let limits = DtdLimits::new().max_entity_len(usize::MAX);
+        let_assert!(Err(error) = table.load_dtd_with_limits(&dtd, limits));
+        assert!(error.kind == DtdErrorKind::SizeLimit);
+    }
+
+    #[test]
+    fn parameter_laughs() {
+        let dtd = format!(
+            r#"<!ENTITY % p0 "<!-- lol -->">{}%p9;"#,
+            laughs("%", "p")
+        );
+        assert!(load_error(&dtd).kind == DtdErrorKind::SizeLimit);
+    }
+
+    #[test]
+    fn parameter_fan_out() {
+        use std::fmt::Write;
+
+        // Each declaration copies `%a;`, which is well below the limit for a
+        // single entity, but together they are far above the total limit.
+        let mut dtd = format!(r#"<!ENTITY % a "{}">"#, "x".repeat(60_000));
+        for i in 0..3000 {
+            writeln!(dtd, r#"<!ENTITY % b{i} "%a;">"#).unwrap();
+        }
+
+        let mut table = EntityTable::empty();
+        let limits = DtdLimits::new().max_total_len(1_000_000);
+        let_assert!(Err(error) = table.load_dtd_with_limits(&dtd, limits));
+        assert!(error.kind == DtdErrorKind::SizeLimit);
+    }
+
+    #[test]
+    fn depth_limit() {
+        let dtd = (0..5)
+            .map(|i| format!(r#"<!ENTITY e{i} "&e{};">"#, i + 1))
+            .chain([r#"<!ENTITY e5 "end">"#.to_owned()])
+            .collect::<String>();
+
+        let mut table = EntityTable::empty();
+        table
+            .load_dtd_with_limits(&dtd, DtdLimits::new().max_depth(6))
+            .unwrap();
+        assert!(table.get("&e0;") == Some("end"));
+
+        // The result doesn’t depend on the order entities are expanded in.
+        for dtd in [dtd.clone(), dtd.replace("e0", "z0")] {
+            let mut table = EntityTable::empty();
+            let_assert!(
+                Err(error) = table
+                    .load_dtd_with_limits(&dtd, DtdLimits::new().max_depth(5))
+            );
+            assert!(error.kind == DtdErrorKind::DepthLimit);
+        }
+    }
+
+    #[test]
+    fn nested_parameter_depth_limit() {
+        let dtd = r#"<!ENTITY % a "<!ENTITY x 'x'>"> <!ENTITY % b "%a;">
+            <!ENTITY % c "&#37;a;"> %c;"#;
+        let mut table = EntityTable::empty();
+        let_assert!(
+            Err(error) =
+                table.load_dtd_with_limits(dtd, DtdLimits::new().max_depth(1))
+        );
+        assert!(error.kind == DtdErrorKind::DepthLimit);
+        table
+            .load_dtd_with_limits(dtd, DtdLimits::new().max_depth(2))
+            .unwrap();
+        assert!(table.get("&x;") == Some("x"));
+    }
+
+    #[test]
+    fn error_display() {
+        let error = DtdError {
+            kind: DtdErrorKind::UndefinedEntity("&a;".to_owned()),
+            offset: 3,
+        };
+        assert!(
+            error.to_string() == "invalid DTD at byte 3: undefined entity &a;"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Unescape with a callback for unknown named references.
+
+use super::{internal, Context, DefaultMatcher};
+use std::borrow::Cow;
+
+/// Expand all valid entities in a given context, and ask `fallback` for the
+/// expansion of unknown named references.
+///
+/// This works like [`unescape_in()`](super::unescape_in), except that every
+/// `&name;` that isn’t a standard entity is passed to `fallback` as `name`
+/// (without `&` and `;`). If `fallback` returns `Some(expansion)`, the
+/// reference is replaced with it. Otherwise, the reference is left as it is.
+///
+/// ```rust
+/// use htmlize::{unescape_with_fallback, Context};
+/// use std::borrow::Cow;
+/// # use assert2::assert;
+///
+/// let unescaped = unescape_with_fallback(
+///     "Dear &username;, &lt;3 &signature; &bogus;",
+///     Context::General,
+///     |name| match name {
+///         "username" => Some("Alice".into()),
+///         "signature" => Some(Cow::Owned(format!("from {}", "Bob"))),
+///         _ => None,
+///     },
+/// );
+/// assert!(unescaped == "Dear Alice, <3 from Bob &bogus;");
+/// ```
+///
+/// `fallback` is only called for names made of ASCII letters and digits that
+/// end with `;`. It is not called if a standard entity without a semicolon is
+/// a prefix of the reference: `&notice;` expands to `¬ice;`, just like it does
+/// in [`unescape_in()`](super::unescape_in). In [`Context::Attribute`] that
+/// rule doesn’t apply, so `&notice;` is passed to `fallback`.
+///
+/// `fallback` can also be used to record unknown names:
+///
+/// ```rust
+/// use htmlize::{unescape_with_fallback, Context};
+/// # use assert2::assert;
+///
+/// let mut unknown = Vec::new();
+/// let unescaped =
+///     unescape_with_fallback("&foo; &amp; &bar;", Context::General, |name| {
+///         unknown.push(name.to_owned());
+///         None
+///     });
+/// assert!(unescaped == "&foo; & &bar;");
+/// assert!(unknown == ["foo", "bar"]);
+/// ```
+///
+/// To work with bytes (`[u8]`) instead of strings, see
+/// [`unescape_bytes_with_fallback()`].
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+pub fn unescape_with_fallback<'a, 'c, S, F>(
+    escaped: S,
+    context: Context,
+    mut fallback: F,
+) -> Cow<'a, str>
+where
+    S: Into<Cow<'a, str>>,
+    F: FnMut(&str) -> Option<Cow<'c, str>>,
+{
+    let escaped = escaped.into();
+    let buffer = unescape_fallback_internal(
+        escaped.as_bytes(),
+        context,
+        &mut |name: &[u8]| {
+            // Names are always ASCII.
+            let name = std::str::from_utf8(name).unwrap();
+            fallback(name).map(|expansion| match expansion {
+                Cow::Borrowed(expansion) => expansion.as_bytes().into(),
+                Cow::Owned(expansion) => expansion.into_bytes().into(),
+            })
+        },
+    );
+    match buffer {
+        Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+        None => escaped,
+    }
+}
+
+/// Expand all valid entities in a given context, and ask `fallback` for the
+/// expansion of unknown named references.
+///
+/// This is the same as [`unescape_with_fallback()`], except that it works on
+/// bytes rather than strings.
+///
+/// ```rust
+/// use htmlize::{unescape_bytes_with_fallback, Context};
+/// # use assert2::assert;
+///
+/// assert!(
+///     unescape_bytes_with_fallback(
+///         &b"&lt;\xFF&nul;"[..],
+///         Context::General,
+///         |name| (name == b"nul").then(|| b"\0"[..].into()),
+///     ) == &b"<\xFF\0"[..]
+/// );
+/// ```
+pub fn unescape_bytes_with_fallback<'a, 'c, S, F>(
+    escaped: S,
+    context: Context,
+    mut fallback: F,
+) -> Cow<'a, [u8]>
+where
+    S: Into<Cow<'a, [u8]>>,
+    F: FnMut(&[u8]) -> Option<Cow<'c, [u8]>>,
+{
+    let escaped = escaped.into();
+    match unescape_fallback_internal(&escaped, context, &mut fallback) {
+        Some(buffer) => buffer.into(),
+        None => escaped,
+    }
+}
+
+/// A closure that expands an unknown named reference.
+type Fallback<'f, 'c> = dyn FnMut(&[u8]) -> Option<Cow<'c, [u8]>> + 'f;
+
+/// Dispatch to [`unescape_fallback()`] for `context`.
+fn unescape_fallback_internal(
+    escaped: &[u8],
+    context: Context,
+    fallback: &mut Fallback<'_, '_>,
+) -> Option<Vec<u8>> {
+    match context {
+        Context::General => unescape_fallback::<(
+            DefaultMatcher,
+            internal::ContextGeneral,
+        )>(escaped, fallback),
+        Context::Attribute => unescape_fallback::<(
+            DefaultMatcher,
+            internal::ContextAttribute,
+        )>(escaped, fallback),
+    }
+}
+
+/// Unescape `escaped`, calling `fallback` for unknown named references.
+///
+/// Returns `None` if no changes would be made.
+fn unescape_fallback<M: internal::Matcher>(
+    escaped: &[u8],
+    fallback: &mut Fallback<'_, '_>,
+) -> Option<Vec<u8>> {
+    internal::replace_references(escaped, |start| {
+        let rest = &escaped[start..];
+        internal::match_entity_len::<M>(rest).or_else(|| {
+            let name = unknown_name(rest)?;
+            // Include the `&` and the `;`.
+            let len = name.len().checked_add(2)?;
+            fallback(name).map(|expansion| (len, expansion))
+        })
+    })
+}
+
+/// Get `name` from a reference like `&name;` at the start of `rest`.
+fn unknown_name(rest: &[u8]) -> Option<&[u8]> {
+    let rest = rest.strip_prefix(b"&")?;
+    let len = rest.iter().position(|c| !c.is_ascii_alphanumeric())?;
+    match rest.split_at(len) {
+        (name, [b';', ..]) if !name.is_empty() => Some(name),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unescape_in;
+    use assert2::assert;
+
+    /// Unescape `input` in general context with `fallback`.
+    fn with_fallback<'c>(
+        input: &str,
+        fallback: impl FnMut(&str) -> Option<Cow<'c, str>>,
+    ) -> String {
+        unescape_with_fallback(input, Context::General, fallback).into_owned()
+    }
+
+    #[test]
+    fn no_fallback_matches_unescape_in() {
+        let input = include_str!("../../tests/corpus/html-escaped.txt");
+        for context in [Context::General, Context::Attribute] {
+            assert!(
+                unescape_with_fallback(input, context, |_| None)
+                    == unescape_in(input, context)
+            );
+        }
+    }
+
+    #[test]
+    fn substitute() {
+        assert!(
+            with_fallback("&a;&b;&c;&amp;", |name| match name {
+                "a" => Some("1".into()),
+                "b" => Some("".into()),
+                _ => None,
+            }) == "1&c;&"
+        );
+    }
+
+    #[test]
+    fn only_unknown_names() {
+        let mut names = Vec::new();
+        let output = with_fallback(
+            "&lt;&foo;&Foo9;&#38;&#xZZ;&bar &;&a-b;&amp;&x;",
+            |name| {
+                names.push(name.to_owned());
+                Some("!".into())
+            },
+        );
+        assert!(names == ["foo", "Foo9", "x"]);
+        assert!(output == "<!!&&#xZZ;&bar &;&a-b;&!");
+    }
+
+    #[test]
+    fn prefix_without_semicolon() {
+        let mut names = Vec::new();
+        let mut fallback = |name: &str| {
+            names.push(name.to_owned());
+            None
+        };
+        assert!(
+            unescape_with_fallback("&notice;", Context::General, &mut fallback)
+                == "¬ice;"
+        );
+        assert!(
+            unescape_with_fallback(
+                "&notice;",
+                Context::Attribute,
+                &mut fallback
+            ) == "&notice;"
+        );
+        assert!(names == ["notice"]);
+    }
+
+    #[test]
+    fn expansion_is_not_unescaped() {
+        assert!(
+            with_fallback("&x;&y;", |name| (name == "x").then(|| "&y;".into()))
+                == "&y;&y;"
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        assert!(
+            unescape_bytes_with_fallback(
+                &b"\xFF&bad;&ok;"[..],
+                Context::General,
+                |name| (name == b"bad").then(|| b"\xFE"[..].into())
+            ) == &b"\xFF\xFE&ok;"[..]
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        assert!(matches!(
+            unescape_with_fallback("&foo; &", Context::General, |_| None),
+            Cow::Borrowed(_)
+        ));
+    }
+}
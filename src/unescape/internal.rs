@@ -35,6 +35,83 @@ pub fn unescape_bytes_in<'a, M: Matcher, S: Into<Cow<'a, [u8]>>>(
     }
 }
 
+/// See [`super::unescape_in_place()`].
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8.
+pub fn unescape_in_place<M: Matcher>(_matcher: M, escaped: &mut String) {
+    let mut buffer = std::mem::take(escaped).into_bytes();
+    unescape_in_place_internal::<M>(&mut buffer);
+    *escaped = String::from_utf8(buffer).unwrap();
+}
+
+/// See [`super::unescape_bytes_in_place()`].
+pub fn unescape_bytes_in_place<M: Matcher>(_matcher: M, escaped: &mut Vec<u8>) {
+    unescape_in_place_internal::<M>(escaped);
+}
+
+/// Unescape `buffer` in place.
+///
+/// Unescaped output is written over the start of `buffer` as the input is
+/// read. The output only catches up with the input when an expansion is
+/// longer than its reference. In that case, the rest of the output is written
+/// to a new buffer, which replaces `buffer`.
+#[allow(
+    clippy::arithmetic_side_effects,
+    reason = "write <= read <= search <= buffer.len()"
+)]
+fn unescape_in_place_internal<M: Matcher>(buffer: &mut Vec<u8>) {
+    // Everything before `write` is output. Everything from `read` on is input
+    // that hasn’t been copied to the output yet.
+    let mut write = 0;
+    let mut read = 0;
+    let mut search = 0;
+    while let Some(offset) = memchr::memchr(b'&', &buffer[search..]) {
+        let start = search + offset;
+        let mut byte_iter = buffer[start..].iter();
+        let expansion = if let Some(expansion) = M::match_entity(&mut byte_iter)
+        {
+            expansion
+        } else {
+            search = start + 1;
+            continue;
+        };
+        let end = buffer.len() - byte_iter.as_slice().len();
+
+        if write != read {
+            buffer.copy_within(read..start, write);
+        }
+        write += start - read;
+
+        if write + expansion.len() <= end {
+            buffer[write..write + expansion.len()].copy_from_slice(&expansion);
+            write += expansion.len();
+            read = end;
+        } else {
+            // The expansion is longer than the reference, e.g. `&nGg;`, so
+            // there isn’t room for it. Shifting the rest of `buffer` for every
+            // such reference would be quadratic, so finish in a new buffer.
+            let rest = &buffer[end..];
+            let mut output = Vec::with_capacity(buffer.len());
+            output.extend_from_slice(&buffer[..write]);
+            output.extend_from_slice(&expansion);
+            match unescape_in_internal::<M>(rest) {
+                Some(unescaped) => output.extend_from_slice(&unescaped),
+                None => output.extend_from_slice(rest),
+            }
+            *buffer = output;
+            return;
+        }
+        search = read;
+    }
+
+    if write != read {
+        buffer.copy_within(read.., write);
+        buffer.truncate(write + buffer.len() - read);
+    }
+}
+
 /// Code that actually does the unescaping.
 ///
 /// Returns `None` if no changes would be made.
@@ -82,6 +159,112 @@ fn unescape_in_internal<M: Matcher>(escaped: &[u8]) -> Option<Vec<u8>> {
     None
 }
 
+/// Unescape `escaped` and append the result to `buffer`.
+///
+/// The end of `escaped` is treated as the end of the input.
+pub fn unescape_into<M: Matcher>(escaped: &[u8], buffer: &mut Vec<u8>) {
+    let mut last_end = 0;
+    for i in memchr::memchr_iter(b'&', escaped) {
+        let mut byte_iter = escaped[i..].iter();
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "byte_iter.as_slice().len() has to be < escaped.len()"
+        )]
+        if let Some(expansion) = M::match_entity(&mut byte_iter) {
+            buffer.extend_from_slice(&escaped[last_end..i]);
+            buffer.extend_from_slice(&expansion);
+            last_end = escaped.len() - byte_iter.as_slice().len();
+        }
+    }
+
+    buffer.extend_from_slice(&escaped[last_end..]);
+}
+
+/// Replace the references in `escaped` that `expand` chooses.
+///
+/// `expand` is called with the index of each `&` that isn’t inside a
+/// reference that was already replaced. It returns the length of the reference
+/// that starts there along with its expansion, or `None` to leave the `&` as
+/// it is.
+///
+/// Returns `None` if no changes would be made.
+pub fn replace_references<'e, F>(
+    escaped: &[u8],
+    mut expand: F,
+) -> Option<Vec<u8>>
+where
+    F: FnMut(usize) -> Option<(usize, Cow<'e, [u8]>)>,
+{
+    let result = try_replace_references(escaped, |start| {
+        Ok::<_, std::convert::Infallible>(expand(start))
+    });
+    match result {
+        Ok(buffer) => buffer,
+        Err(never) => match never {},
+    }
+}
+
+/// Replace the references in `escaped` that `expand` chooses, stopping at the
+/// first error.
+///
+/// This is the same as [`replace_references()`], except that `expand` may
+/// fail. Returns `Ok(None)` if no changes would be made.
+///
+/// # Errors
+///
+/// Returns the first error returned by `expand`.
+pub fn try_replace_references<'e, E, F>(
+    escaped: &[u8],
+    mut expand: F,
+) -> Result<Option<Vec<u8>>, E>
+where
+    F: FnMut(usize) -> Result<Option<(usize, Cow<'e, [u8]>)>, E>,
+{
+    let mut buffer: Option<Vec<u8>> = None;
+    let mut last_end = 0;
+    for start in memchr::memchr_iter(b'&', escaped) {
+        if start < last_end {
+            // Inside a reference that was already replaced.
+            continue;
+        }
+
+        if let Some((len, expansion)) = expand(start)? {
+            let buffer =
+                buffer.get_or_insert_with(|| Vec::with_capacity(escaped.len()));
+            buffer.extend_from_slice(&escaped[last_end..start]);
+            buffer.extend_from_slice(&expansion);
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "len <= escaped.len() - start"
+            )]
+            let end = start + len;
+            last_end = end;
+        }
+    }
+
+    Ok(buffer.map(|mut buffer| {
+        buffer.extend_from_slice(&escaped[last_end..]);
+        buffer
+    }))
+}
+
+/// Match an entity at the start of `rest` with `M`.
+///
+/// Returns the length of the entity and its expansion.
+#[must_use]
+pub fn match_entity_len<M: Matcher>(
+    rest: &[u8],
+) -> Option<(usize, Cow<'static, [u8]>)> {
+    let mut byte_iter = rest.iter();
+    let expansion = M::match_entity(&mut byte_iter)?;
+    #[allow(
+        clippy::arithmetic_side_effects,
+        reason = "byte_iter is a subslice of rest"
+    )]
+    let len = rest.len() - byte_iter.as_slice().len();
+    Some((len, expansion))
+}
+
 /// A Phf-based matcher.
 #[cfg(feature = "unescape")]
 pub struct Phf;
@@ -111,8 +294,11 @@ pub trait Matcher {
     ///
     /// This version uses matchgen instead of the `ENTITIES` map. It is faster
     /// at runtime but slower to build.
-    fn match_entity<'a>(iter: &'a mut slice::Iter<u8>)
-        -> Option<Cow<'a, [u8]>>;
+    fn match_entity(iter: &mut slice::Iter<u8>) -> Option<Cow<'static, [u8]>>;
+
+    /// Length of the longest named entity this can match, including ‘&’ and
+    /// possibly ‘;’.
+    const ENTITY_MAX_LENGTH: usize;
 }
 
 // Include function to match entities at the start of an iterator. Used in
@@ -124,11 +310,15 @@ pub trait Matcher {
 #[cfg(feature = "unescape_fast")]
 include!(concat!(env!("OUT_DIR"), "/matcher.rs"));
 
+// Include `const MATCHGEN_MAX_LENGTH: usize`.
+#[cfg(feature = "unescape_fast")]
+include!(concat!(env!("OUT_DIR"), "/matcher_len.rs"));
+
 #[cfg(feature = "unescape_fast")]
 impl Matcher for (Matchgen, ContextAttribute) {
-    fn match_entity<'a>(
-        iter: &'a mut slice::Iter<u8>,
-    ) -> Option<Cow<'a, [u8]>> {
+    const ENTITY_MAX_LENGTH: usize = MATCHGEN_MAX_LENGTH;
+
+    fn match_entity(iter: &mut slice::Iter<u8>) -> Option<Cow<'static, [u8]>> {
         assert_peek_eq(iter, Some(b'&'), "match_entity() expected '&'");
 
         if Some(b'#') == peek_n(iter, 1) {
@@ -174,9 +364,9 @@ impl Matcher for (Matchgen, ContextAttribute) {
 
 #[cfg(feature = "unescape_fast")]
 impl Matcher for (Matchgen, ContextGeneral) {
-    fn match_entity<'a>(
-        iter: &'a mut slice::Iter<u8>,
-    ) -> Option<Cow<'a, [u8]>> {
+    const ENTITY_MAX_LENGTH: usize = MATCHGEN_MAX_LENGTH;
+
+    fn match_entity(iter: &mut slice::Iter<u8>) -> Option<Cow<'static, [u8]>> {
         assert_peek_eq(iter, Some(b'&'), "match_entity() expected '&'");
 
         if Some(b'#') == peek_n(iter, 1) {
@@ -210,9 +400,9 @@ const PEEK_MATCH_ERROR: &str = "iter.next() did not match previous peek(iter)";
 
 #[cfg(feature = "unescape")]
 impl Matcher for (Phf, ContextAttribute) {
-    fn match_entity<'a>(
-        iter: &'a mut slice::Iter<u8>,
-    ) -> Option<Cow<'a, [u8]>> {
+    const ENTITY_MAX_LENGTH: usize = crate::ENTITY_MAX_LENGTH;
+
+    fn match_entity(iter: &mut slice::Iter<u8>) -> Option<Cow<'static, [u8]>> {
         use crate::{ENTITIES, ENTITY_MIN_LENGTH};
         assert_peek_eq(iter, Some(b'&'), "match_entity() expected '&'");
 
@@ -282,9 +472,9 @@ impl Matcher for (Phf, ContextAttribute) {
 
 #[cfg(feature = "unescape")]
 impl Matcher for (Phf, ContextGeneral) {
-    fn match_entity<'a>(
-        iter: &'a mut slice::Iter<u8>,
-    ) -> Option<Cow<'a, [u8]>> {
+    const ENTITY_MAX_LENGTH: usize = crate::ENTITY_MAX_LENGTH;
+
+    fn match_entity(iter: &mut slice::Iter<u8>) -> Option<Cow<'static, [u8]>> {
         use crate::{BARE_ENTITY_MAX_LENGTH, ENTITIES, ENTITY_MIN_LENGTH};
         use std::cmp::min;
 
@@ -360,13 +550,126 @@ impl Matcher for (Phf, ContextGeneral) {
     }
 }
 
+/// Find the longest named entity at the start of `input`, which must start
+/// with `&`.
+///
+/// Returns the length of the entity and its expansion. This ignores context;
+/// the caller must check the rules for attributes.
+#[cfg(feature = "unescape_fast")]
+#[must_use]
+pub fn longest_entity(input: &[u8]) -> Option<(usize, &'static [u8])> {
+    let (expansion, rest) = entity_matcher(input);
+    #[allow(
+        clippy::arithmetic_side_effects,
+        reason = "rest is a subslice of input"
+    )]
+    let len = input.len() - rest.len();
+    expansion.map(|(_, expansion)| (len, expansion))
+}
+
+/// Find the longest named entity at the start of `input`, which must start
+/// with `&`.
+///
+/// Returns the length of the entity and its expansion. This ignores context;
+/// the caller must check the rules for attributes.
+#[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+#[must_use]
+pub fn longest_entity(input: &[u8]) -> Option<(usize, &'static [u8])> {
+    use crate::{BARE_ENTITY_MAX_LENGTH, ENTITIES, ENTITY_MIN_LENGTH};
+    use std::cmp::min;
+
+    let mut iter = input.iter();
+    find_longest_candidate(&mut iter);
+    #[allow(
+        clippy::arithmetic_side_effects,
+        reason = "iter is a subslice of input"
+    )]
+    let candidate_len = input.len() - iter.as_slice().len();
+
+    if peek(&iter) == Some(b';') {
+        #[allow(clippy::arithmetic_side_effects, reason = "';' is in input")]
+        let len = candidate_len + 1;
+        if let Some(&expansion) = ENTITIES.get(&input[..len]) {
+            return Some((len, expansion));
+        }
+    }
+
+    // Only bare entities can match a prefix of the candidate.
+    (ENTITY_MIN_LENGTH..=min(candidate_len, BARE_ENTITY_MAX_LENGTH))
+        .rev()
+        .find_map(|len| {
+            ENTITIES
+                .get(&input[..len])
+                .map(|&expansion| (len, expansion))
+        })
+}
+
+// Include `FOLDED_ENTITIES` and `FOLDED_MAX_LENGTH`. Used in
+// `longest_entity_ignore_case()`.
+include!(concat!(env!("OUT_DIR"), "/folded.rs"));
+
+/// Find the longest named entity at the start of `input`, which must start
+/// with `&`, ignoring ASCII case.
+///
+/// This works like [`longest_entity()`], except that `&NBSP;` matches
+/// `&nbsp;`. If more than one entity matches, the lowercase one is preferred;
+/// see `FOLDED_ENTITIES`.
+#[must_use]
+#[allow(
+    clippy::arithmetic_side_effects,
+    reason = "name_len < folded.len() <= FOLDED_MAX_LENGTH"
+)]
+pub fn longest_entity_ignore_case(
+    input: &[u8],
+) -> Option<(usize, &'static [u8])> {
+    let mut buffer = [0_u8; FOLDED_MAX_LENGTH];
+    let folded = &mut buffer[..input.len().min(FOLDED_MAX_LENGTH)];
+    folded.copy_from_slice(&input[..folded.len()]);
+    folded.make_ascii_lowercase();
+
+    // The `&` and any alphanumeric characters after it, then maybe `;`.
+    let mut candidate_len = 1 + folded
+        .iter()
+        .skip(1)
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .count();
+    if folded.get(candidate_len) == Some(&b';') {
+        candidate_len += 1;
+    }
+
+    (2..=candidate_len).rev().find_map(|len| {
+        let name = &folded[..len];
+        FOLDED_ENTITIES
+            .binary_search_by(|(other, _)| (*other).cmp(name))
+            .ok()
+            .map(|i| (len, FOLDED_ENTITIES[i].1))
+    })
+}
+
 /// Match a numeric entity like `&#x20;` or `&#32;`.
-#[allow(clippy::from_str_radix_10)]
-fn match_numeric_entity(
+///
+/// # Panics
+///
+/// Panics if `iter` doesn’t start with `&#`.
+pub fn match_numeric_entity(
     iter: &mut slice::Iter<u8>,
 ) -> Option<Cow<'static, [u8]>> {
-    assert_next_eq(iter, Some(b'&'), "match_numeric_entity() expected '&'");
-    assert_next_eq(iter, Some(b'#'), "match_numeric_entity() expected '#'");
+    parse_numeric_entity(iter).map(correct_numeric_entity)
+}
+
+/// Parse a numeric entity like `&#x20;` or `&#32;`.
+///
+/// Returns the number it refers to, or `u32::MAX` if the number is too large.
+/// Returns `None` if there are no digits, in which case it should not be
+/// expanded.
+///
+/// # Panics
+///
+/// Panics if `iter` doesn’t start with `&#`.
+#[allow(clippy::from_str_radix_10)]
+pub fn parse_numeric_entity(iter: &mut slice::Iter<u8>) -> Option<u32> {
+    assert_next_eq(iter, Some(b'&'), "parse_numeric_entity() expected '&'");
+    assert_next_eq(iter, Some(b'#'), "parse_numeric_entity() expected '#'");
 
     let number = match peek(iter) {
         c @ Some(b'x' | b'X') => {
@@ -395,24 +698,16 @@ fn match_numeric_entity(
     }
 
     match number {
-        Ok(number) => {
-            return Some(correct_numeric_entity(number));
-        }
+        Ok(number) => Some(number),
         Err(error) => match error.kind() {
-            IntErrorKind::PosOverflow => {
-                // Too large a number
-                return Some(REPLACEMENT_CHAR_BYTES.into());
-            }
-            IntErrorKind::Empty => {
-                // No number, e.g. &#; or &#x;. Fall through.
-            }
+            // Too large a number. Anything above 0x10FFFF is out of range.
+            IntErrorKind::PosOverflow => Some(u32::MAX),
+            // No number, e.g. &#; or &#x;. Do not expand.
+            IntErrorKind::Empty => None,
             // Pretty sure this is impossible.
             _ => panic!("error parsing number in numeric entity: {error:?}"),
         },
     }
-
-    // Do not expand.
-    None
 }
 
 /// Unicode replacement character (U+FFFD, “�”).
@@ -428,8 +723,9 @@ pub const REPLACEMENT_CHAR_BYTES: &[u8] = "\u{fffd}".as_bytes();
 /// Calculate the expansion for a numeric entity (after parsing it).
 ///
 /// See <https://html.spec.whatwg.org/multipage/parsing.html#numeric-character-reference-end-state>
+#[must_use]
 #[allow(clippy::match_same_arms)]
-fn correct_numeric_entity(number: u32) -> Cow<'static, [u8]> {
+pub fn correct_numeric_entity(number: u32) -> Cow<'static, [u8]> {
     match number {
         // null-character-reference parse error:
         0x00 => REPLACEMENT_CHAR_BYTES.into(),
@@ -627,12 +923,20 @@ mod tests {
                 #[test]
                 fn [<fast_ $name>]() {
                     assert!(unescape_in((Matchgen, ContextGeneral), $($input)+) == $expected);
+
+                    let mut buffer = String::from($($input)+);
+                    unescape_in_place((Matchgen, ContextGeneral), &mut buffer);
+                    assert!(buffer == $expected);
                 }
 
                 #[cfg(feature = "unescape")]
                 #[test]
                 fn [<slow_ $name>]() {
                     assert!(unescape_in((Phf, ContextGeneral), $($input)+) == $expected);
+
+                    let mut buffer = String::from($($input)+);
+                    unescape_in_place((Phf, ContextGeneral), &mut buffer);
+                    assert!(buffer == $expected);
                 }
             }
         };
@@ -642,12 +946,20 @@ mod tests {
                 #[test]
                 fn [<fast_ $name>]() {
                     assert!(unescape_in((Matchgen, ContextAttribute), $($input)+) == $expected);
+
+                    let mut buffer = String::from($($input)+);
+                    unescape_in_place((Matchgen, ContextAttribute), &mut buffer);
+                    assert!(buffer == $expected);
                 }
 
                 #[cfg(feature = "unescape")]
                 #[test]
                 fn [<slow_ $name>]() {
                     assert!(unescape_in((Phf, ContextAttribute), $($input)+) == $expected);
+
+                    let mut buffer = String::from($($input)+);
+                    unescape_in_place((Phf, ContextAttribute), &mut buffer);
+                    assert!(buffer == $expected);
                 }
             }
         };
@@ -805,6 +1117,49 @@ mod tests {
         include_str!("../../tests/corpus/all-entities-expanded.txt");
     test_both!(all_entities, unescape(ALL_SOURCE) == ALL_EXPANDED);
 
+    test_both!(
+        longer_expansion,
+        unescape("&nGg;&amp;&nLl;") == "\u{22D9}\u{338}&\u{22D8}\u{338}"
+    );
+    test_both!(
+        longer_expansion_shifts,
+        unescape("a&nGg;&lt;b&#x20;&nLl;c&amp;")
+            == "a\u{22D9}\u{338}<b \u{22D8}\u{338}c&"
+    );
+
+    test_both!(
+        many_longer_expansions,
+        unescape(&"&nGg;".repeat(10_000)) == "\u{22D9}\u{338}".repeat(10_000)
+    );
+
+    #[cfg(feature = "unescape_fast")]
+    #[test]
+    fn fast_in_place_invalid_utf8() {
+        let mut buffer = b"\xa1&lt;\xa1".to_vec();
+        unescape_bytes_in_place((Matchgen, ContextGeneral), &mut buffer);
+        assert!(buffer == b"\xa1<\xa1");
+    }
+
+    #[cfg(feature = "unescape")]
+    #[test]
+    fn slow_in_place_invalid_utf8() {
+        let mut buffer = b"\xa1&lt;\xa1".to_vec();
+        unescape_bytes_in_place((Phf, ContextGeneral), &mut buffer);
+        assert!(buffer == b"\xa1<\xa1");
+    }
+
+    #[cfg(feature = "unescape")]
+    #[test]
+    fn in_place_keeps_allocation() {
+        let mut buffer = String::with_capacity(64);
+        buffer.push_str("&amp;&lt;&gt; &#x1F600;");
+        let pointer = buffer.as_ptr();
+        unescape_in_place((Phf, ContextGeneral), &mut buffer);
+        assert!(buffer == "&<> 😀");
+        assert!(buffer.as_ptr() == pointer);
+        assert!(buffer.capacity() == 64);
+    }
+
     #[cfg(feature = "unescape_fast")]
     #[test]
     fn fast_invalid_utf8() {
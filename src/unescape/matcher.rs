@@ -0,0 +1,319 @@
+//! Unescape with a custom set of named entities.
+
+use super::{internal, Context};
+use std::borrow::Cow;
+
+/// A set of named entities that can be used with [`unescape_with()`].
+///
+/// Implement this to unescape with entities other than the standard HTML ones,
+/// or to look them up in a different way. A matcher only deals with named
+/// entities. Numeric entities like `&#x26;` and the special rules for
+/// [`Context::Attribute`] are handled by [`unescape_with()`], so the same
+/// matcher works in any context.
+///
+/// ```rust
+/// use htmlize::{unescape_with, Context, EntityMatcher};
+/// use std::borrow::Cow;
+/// # use assert2::assert;
+///
+/// /// Only knows `&heart;` and `&heart` (without a semicolon).
+/// struct Hearts;
+///
+/// impl EntityMatcher for Hearts {
+///     fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)> {
+///         if input.starts_with(b"&heart;") {
+///             Some((7, "♥".into()))
+///         } else if input.starts_with(b"&heart") {
+///             Some((6, "♥".into()))
+///         } else {
+///             None
+///         }
+///     }
+/// }
+///
+/// assert!(unescape_with(&Hearts, "&heart;&#9829; &amp;", Context::General) == "♥♥ &amp;");
+/// assert!(unescape_with(&Hearts, "&heart &hearts", Context::General) == "♥ ♥s");
+/// assert!(unescape_with(&Hearts, "&heart &hearts", Context::Attribute) == "♥ &hearts");
+/// ```
+pub trait EntityMatcher {
+    /// Match the longest named entity at the start of `input`.
+    ///
+    /// `input` always starts with `&` and continues to the end of the text
+    /// being unescaped. If it starts with an entity, this returns its length
+    /// in bytes, including the `&` and the `;` (if any), along with its
+    /// expansion. Otherwise, it returns `None`.
+    ///
+    /// If more than one entity matches, e.g. `&times` and `&timesbar;` in
+    /// `&timesbar;`, this should return the longest.
+    fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)>;
+}
+
+impl<M: EntityMatcher + ?Sized> EntityMatcher for &M {
+    fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)> {
+        (**self).match_entity(input)
+    }
+}
+
+/// The named entities defined by the [WHATWG HTML spec][spec].
+///
+/// These are the entities used by [`unescape_in()`](super::unescape_in), so
+/// `unescape_with(&HtmlEntities, …)` is equivalent to `unescape_in(…)`. It may
+/// be useful to build a custom [`EntityMatcher`] on top of this one.
+///
+/// [spec]: https://html.spec.whatwg.org/multipage/named-characters.html#named-character-references
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HtmlEntities;
+
+impl EntityMatcher for HtmlEntities {
+    fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)> {
+        internal::longest_entity(input).map(|(len, expansion)| {
+            // All expansions are valid UTF-8.
+            (len, std::str::from_utf8(expansion).unwrap().into())
+        })
+    }
+}
+
+/// Expand all valid entities in a given context using a custom set of named
+/// entities.
+///
+/// This works like [`unescape_in()`](super::unescape_in), except that named
+/// entities are found with `matcher`. Numeric entities are always expanded.
+/// See [`EntityMatcher`] for an example.
+///
+/// To work with bytes (`[u8]`) instead of strings, see
+/// [`unescape_bytes_with()`].
+///
+/// # Panics
+///
+/// Panics if `matcher` returns a length that is longer than its input or that
+/// doesn’t end on a character boundary.
+pub fn unescape_with<'a, M, S>(
+    matcher: &M,
+    escaped: S,
+    context: Context,
+) -> Cow<'a, str>
+where
+    M: EntityMatcher + ?Sized,
+    S: Into<Cow<'a, str>>,
+{
+    let escaped = escaped.into();
+    match unescape_with_internal(matcher, escaped.as_bytes(), context) {
+        Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+        None => escaped,
+    }
+}
+
+/// Expand all valid entities in a given context using a custom set of named
+/// entities.
+///
+/// This is the same as [`unescape_with()`], except that it works on bytes
+/// rather than strings.
+///
+/// ```rust
+/// use htmlize::{unescape_bytes_with, Context, HtmlEntities};
+/// # use assert2::assert;
+///
+/// assert!(
+///     unescape_bytes_with(&HtmlEntities, &b"&lt;\xFF&gt;"[..], Context::General)
+///         == &b"<\xFF>"[..]
+/// );
+/// ```
+///
+/// # Panics
+///
+/// Panics if `matcher` returns a length that is longer than its input.
+pub fn unescape_bytes_with<'a, M, S>(
+    matcher: &M,
+    escaped: S,
+    context: Context,
+) -> Cow<'a, [u8]>
+where
+    M: EntityMatcher + ?Sized,
+    S: Into<Cow<'a, [u8]>>,
+{
+    let escaped = escaped.into();
+    match unescape_with_internal(matcher, &escaped, context) {
+        Some(buffer) => buffer.into(),
+        None => escaped,
+    }
+}
+
+/// Code that actually does the unescaping.
+///
+/// Returns `None` if no changes would be made.
+fn unescape_with_internal<M: EntityMatcher + ?Sized>(
+    matcher: &M,
+    escaped: &[u8],
+    context: Context,
+) -> Option<Vec<u8>> {
+    internal::replace_references(escaped, |start| {
+        match_reference(matcher, &escaped[start..], context)
+    })
+}
+
+/// Match a numeric or named entity at the start of `rest`, which starts with
+/// `&`.
+///
+/// Returns the length of the entity and its expansion.
+///
+/// # Panics
+///
+/// Panics if `matcher` returns a length that is longer than `rest`.
+fn match_reference<'m, M: EntityMatcher + ?Sized>(
+    matcher: &'m M,
+    rest: &[u8],
+    context: Context,
+) -> Option<(usize, Cow<'m, [u8]>)> {
+    if rest.get(1) == Some(&b'#') {
+        let mut iter = rest.iter();
+        let expansion = internal::match_numeric_entity(&mut iter)?;
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "iter is a subslice of rest"
+        )]
+        let len = rest.len() - iter.as_slice().len();
+        return Some((len, expansion));
+    }
+
+    let (len, expansion) = matcher.match_entity(rest)?;
+    assert!(len <= rest.len(), "EntityMatcher matched past end of input");
+    let entity = &rest[..len];
+    if entity.is_empty() {
+        return None;
+    }
+
+    // In an attribute, entities ending with an alphanumeric character or '='
+    // instead of ';' are passed through without expansion.
+    //
+    // https://html.spec.whatwg.org/multipage/parsing.html#named-character-reference-state
+    if context == Context::Attribute && entity.last() != Some(&b';') {
+        if let Some(&next) = rest.get(len) {
+            if next == b'=' || next.is_ascii_alphanumeric() {
+                return None;
+            }
+        }
+    }
+
+    Some((
+        len,
+        match expansion {
+            Cow::Borrowed(expansion) => expansion.as_bytes().into(),
+            Cow::Owned(expansion) => expansion.into_bytes().into(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{unescape_bytes_in, unescape_in};
+    use assert2::assert;
+
+    /// A matcher with a few entities for testing.
+    struct Small;
+
+    impl EntityMatcher for Small {
+        fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)> {
+            [("&a;", "A"), ("&a", "a"), ("&abc;", "ABC"), ("&o", "o")]
+                .iter()
+                .filter(|(name, _)| input.starts_with(name.as_bytes()))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(name, expansion)| (name.len(), (*expansion).into()))
+        }
+    }
+
+    #[test]
+    fn custom_general() {
+        assert!(
+            unescape_with(&Small, "&a;&a &ab &abc; &o= &x;", Context::General)
+                == "Aa ab ABC o= &x;"
+        );
+    }
+
+    #[test]
+    fn custom_attribute() {
+        assert!(
+            unescape_with(
+                &Small,
+                "&a;&a &ab &abc; &o= &o! &x;",
+                Context::Attribute
+            ) == "Aa &ab ABC &o= o! &x;"
+        );
+    }
+
+    #[test]
+    fn custom_numeric() {
+        for context in [Context::General, Context::Attribute] {
+            assert!(
+                unescape_with(&Small, "&#65;&#x42 &#; &#0;", context)
+                    == "AB &#; \u{fffd}"
+            );
+        }
+    }
+
+    #[test]
+    fn custom_borrowed() {
+        assert!(matches!(
+            unescape_with(&Small, "&b; &#;", Context::General),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn dyn_matcher() {
+        let matcher: &dyn EntityMatcher = &Small;
+        assert!(unescape_with(matcher, "&a;", Context::General) == "A");
+        assert!(unescape_with(&matcher, "&a;", Context::General) == "A");
+    }
+
+    #[test]
+    fn html_entities_prefixes() {
+        assert!(
+            HtmlEntities.match_entity(b"&timesbar;x") == Some((10, "⨱".into()))
+        );
+        assert!(
+            HtmlEntities.match_entity(b"&timesbarx") == Some((6, "×".into()))
+        );
+        assert!(HtmlEntities.match_entity(b"&notit;") == Some((4, "¬".into())));
+        assert!(HtmlEntities.match_entity(b"&foo;") == None);
+        assert!(HtmlEntities.match_entity(b"&") == None);
+    }
+
+    /// Check that `HtmlEntities` gives the same results as `unescape_in()`.
+    fn check_html_entities(input: &str) {
+        for context in [Context::General, Context::Attribute] {
+            assert!(
+                unescape_with(&HtmlEntities, input, context)
+                    == unescape_in(input, context),
+                "{context:?}"
+            );
+            assert!(
+                unescape_bytes_with(&HtmlEntities, input.as_bytes(), context)
+                    == unescape_bytes_in(input.as_bytes(), context),
+                "{context:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn html_entities_edge_cases() {
+        check_html_entities(
+            "&times &times; &timesX &times= &times# &timesbar &notit; \
+            &amp &ampx &amp= &#x1F600; &#128512 &# &; & &&amp;",
+        );
+    }
+
+    #[test]
+    fn html_entities_all() {
+        check_html_entities(include_str!(
+            "../../tests/corpus/all-entities-source.txt"
+        ));
+    }
+
+    #[test]
+    fn html_entities_html() {
+        check_html_entities(include_str!(
+            "../../tests/corpus/html-escaped.txt"
+        ));
+    }
+}
@@ -227,6 +227,109 @@ pub fn unescape_bytes_in<'a, S: Into<Cow<'a, [u8]>>>(
     }
 }
 
+/// Expand all valid entities in a given context, reusing the allocation.
+///
+/// This works exactly like [`unescape_in()`], but it overwrites `escaped` with
+/// the result instead of allocating a new `String`. This is useful when
+/// unescaping many strings that are no longer needed afterward.
+///
+/// ```rust
+/// use htmlize::{unescape_in_place, Context};
+/// # use assert2::assert;
+///
+/// let mut text = String::from("3 &times; 4 &gt; 10");
+/// unescape_in_place(&mut text, Context::General);
+/// assert!(text == "3 × 4 > 10");
+/// ```
+///
+/// Almost every reference is at least as long as its expansion, so this
+/// generally doesn’t allocate. The exceptions are `&nGg;` (≫⃒) and `&nLl;`
+/// (≪⃒), which may cause `escaped` to grow.
+///
+/// To work with bytes (`Vec<u8>`) instead of strings, see
+/// [`unescape_bytes_in_place()`].
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+pub fn unescape_in_place(escaped: &mut String, context: Context) {
+    match context {
+        Context::Attribute => {
+            #[cfg(feature = "unescape_fast")]
+            internal::unescape_in_place(
+                (internal::Matchgen, internal::ContextAttribute),
+                escaped,
+            );
+
+            #[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+            internal::unescape_in_place(
+                (internal::Phf, internal::ContextAttribute),
+                escaped,
+            );
+        }
+        Context::General => {
+            #[cfg(feature = "unescape_fast")]
+            internal::unescape_in_place(
+                (internal::Matchgen, internal::ContextGeneral),
+                escaped,
+            );
+
+            #[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+            internal::unescape_in_place(
+                (internal::Phf, internal::ContextGeneral),
+                escaped,
+            );
+        }
+    }
+}
+
+/// Expand all valid entities in a given context, reusing the allocation.
+///
+/// This works exactly like [`unescape_bytes_in()`], but it overwrites
+/// `escaped` with the result instead of allocating a new `Vec`. See
+/// [`unescape_in_place()`] for more information.
+///
+/// ```rust
+/// use htmlize::{unescape_bytes_in_place, Context};
+/// # use assert2::assert;
+///
+/// let mut bytes = b"&lt;\xFF&gt;".to_vec();
+/// unescape_bytes_in_place(&mut bytes, Context::General);
+/// assert!(bytes == b"<\xFF>");
+/// ```
+///
+/// To work with `String` instead of bytes, see [`unescape_in_place()`].
+pub fn unescape_bytes_in_place(escaped: &mut Vec<u8>, context: Context) {
+    match context {
+        Context::Attribute => {
+            #[cfg(feature = "unescape_fast")]
+            internal::unescape_bytes_in_place(
+                (internal::Matchgen, internal::ContextAttribute),
+                escaped,
+            );
+
+            #[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+            internal::unescape_bytes_in_place(
+                (internal::Phf, internal::ContextAttribute),
+                escaped,
+            );
+        }
+        Context::General => {
+            #[cfg(feature = "unescape_fast")]
+            internal::unescape_bytes_in_place(
+                (internal::Matchgen, internal::ContextGeneral),
+                escaped,
+            );
+
+            #[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+            internal::unescape_bytes_in_place(
+                (internal::Phf, internal::ContextGeneral),
+                escaped,
+            );
+        }
+    }
+}
+
 // Need these to be public for benchmarks
 #[cfg(all(feature = "bench", not(doc)))]
 pub mod internal;
@@ -235,3 +338,42 @@ pub mod internal;
 mod internal;
 
 pub use internal::REPLACEMENT_CHAR_BYTES;
+
+mod diagnostic;
+pub use diagnostic::*;
+
+mod matcher;
+pub use matcher::*;
+
+mod options;
+pub use options::*;
+
+mod selective;
+pub use selective::*;
+
+mod fallback;
+pub use fallback::*;
+
+mod normalize;
+pub use normalize::*;
+
+mod stream;
+pub use stream::*;
+
+feature! {
+    #![feature = "entities"]
+
+    mod dtd;
+    pub use dtd::*;
+
+    mod table;
+    pub use table::*;
+}
+
+/// The matcher used by the public functions, depending on enabled features.
+#[cfg(feature = "unescape_fast")]
+type DefaultMatcher = internal::Matchgen;
+
+/// The matcher used by the public functions, depending on enabled features.
+#[cfg(all(feature = "unescape", not(feature = "unescape_fast")))]
+type DefaultMatcher = internal::Phf;
@@ -0,0 +1,343 @@
+//! Rewrite escaped text in a canonical form.
+
+use super::{internal, Context, DefaultMatcher};
+use std::borrow::Cow;
+
+/// How [`normalize_escaped()`] writes the characters that must be escaped.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Style {
+    /// Named entities, e.g. `&amp;` and `&lt;`.
+    Named,
+    /// Decimal numeric references, e.g. `&#38;` and `&#60;`.
+    Decimal,
+    /// Hexadecimal numeric references, e.g. `&#x26;` and `&#x3C;`.
+    Hex,
+}
+
+impl Style {
+    /// References to each character in [`MUST_ESCAPE`], in the same order.
+    ///
+    /// There is no named entity for `\r`, so it always uses a numeric
+    /// reference.
+    const fn references(self) -> [&'static [u8]; 5] {
+        match self {
+            Self::Named => [b"&amp;", b"&lt;", b"&gt;", b"&#13;", b"&quot;"],
+            Self::Decimal => [b"&#38;", b"&#60;", b"&#62;", b"&#13;", b"&#34;"],
+            Self::Hex => [b"&#x26;", b"&#x3C;", b"&#x3E;", b"&#xD;", b"&#x22;"],
+        }
+    }
+}
+
+/// The characters that may need to be escaped. `"` only needs to be escaped in
+/// [`Context::Attribute`].
+///
+/// `\r` only needs to be escaped if it came from a reference. HTML turns a raw
+/// `\r` into `\n`, so writing `&#13;` as a raw `\r` would change its meaning.
+const MUST_ESCAPE: [u8; 5] = [b'&', b'<', b'>', b'\r', b'"'];
+
+/// Get the characters that must be escaped in `context`.
+fn must_escape(context: Context) -> &'static [u8] {
+    match context {
+        Context::General => &MUST_ESCAPE[..4],
+        Context::Attribute => &MUST_ESCAPE,
+    }
+}
+
+/// Rewrite escaped text with one consistent spelling for every character.
+///
+/// This parses references the same way as
+/// [`unescape_in()`](super::unescape_in), then escapes only the characters
+/// that must be escaped in `context` using `style`. Everything else is written
+/// as the raw character. Text that means the same thing always normalizes to
+/// the same output, so it can be compared or hashed.
+///
+/// In [`Context::General`], `&`, `<`, and `>` are escaped, just like
+/// [`escape_text()`](crate::escape_text()) does. In [`Context::Attribute`],
+/// `"` is escaped too, just like
+/// [`escape_attribute()`](crate::escape_attribute()) does.
+///
+/// A carriage return written as a reference, e.g. `&#13;`, stays a reference,
+/// since HTML turns a raw carriage return into a line feed. A raw carriage
+/// return is left as it is.
+///
+/// ```rust
+/// use htmlize::{normalize_escaped, Context, Style};
+/// # use assert2::assert;
+///
+/// let input = "&#x26;&AMP;&#38;&amp &eacute;&#233; &lt;&#x3e; \"&quot;";
+/// assert!(
+///     normalize_escaped(input, Context::General, Style::Named)
+///         == "&amp;&amp;&amp;&amp; éé &lt;&gt; \"\""
+/// );
+/// assert!(
+///     normalize_escaped(input, Context::Attribute, Style::Decimal)
+///         == "&#38;&#38;&#38;&#38; éé &#60;&#62; &#34;&#34;"
+/// );
+/// assert!(
+///     normalize_escaped(input, Context::Attribute, Style::Hex)
+///         == "&#x26;&#x26;&#x26;&#x26; éé &#x3C;&#x3E; &#x22;&#x22;"
+/// );
+/// ```
+///
+/// A bare `&` that doesn’t start a reference is escaped as well, so `a & b`
+/// and `&bogus;` become `a &amp; b` and `&amp;bogus;`.
+///
+/// If `escaped` is already in canonical form, it is returned as is.
+///
+/// To work with bytes (`[u8]`) instead of strings, see
+/// [`normalize_escaped_bytes()`].
+///
+/// # Panics
+///
+/// Panics if the normalized bytes are invalid UTF-8. This should be
+/// impossible.
+pub fn normalize_escaped<'a, S: Into<Cow<'a, str>>>(
+    escaped: S,
+    context: Context,
+    style: Style,
+) -> Cow<'a, str> {
+    let escaped = escaped.into();
+    match normalize_internal(escaped.as_bytes(), context, style) {
+        Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+        None => escaped,
+    }
+}
+
+/// Rewrite escaped bytes with one consistent spelling for every character.
+///
+/// This is the same as [`normalize_escaped()`], except that it works on bytes
+/// rather than strings. Invalid UTF-8 is passed through.
+///
+/// ```rust
+/// use htmlize::{normalize_escaped_bytes, Context, Style};
+/// # use assert2::assert;
+///
+/// assert!(
+///     normalize_escaped_bytes(
+///         &b"&#60;\xFF&eacute;&"[..],
+///         Context::General,
+///         Style::Named,
+///     ) == &b"&lt;\xFF\xC3\xA9&amp;"[..]
+/// );
+/// ```
+pub fn normalize_escaped_bytes<'a, S: Into<Cow<'a, [u8]>>>(
+    escaped: S,
+    context: Context,
+    style: Style,
+) -> Cow<'a, [u8]> {
+    let escaped = escaped.into();
+    match normalize_internal(&escaped, context, style) {
+        Some(buffer) => buffer.into(),
+        None => escaped,
+    }
+}
+
+/// Dispatch to [`normalize()`] for `context`.
+fn normalize_internal(
+    escaped: &[u8],
+    context: Context,
+    style: Style,
+) -> Option<Vec<u8>> {
+    let characters = must_escape(context);
+    let references = &style.references()[..characters.len()];
+    match context {
+        Context::General => normalize::<(
+            DefaultMatcher,
+            internal::ContextGeneral,
+        )>(escaped, characters, references),
+        Context::Attribute => normalize::<(
+            DefaultMatcher,
+            internal::ContextAttribute,
+        )>(escaped, characters, references),
+    }
+}
+
+/// Unescape `escaped`, then escape `characters` with `references`.
+///
+/// Returns `None` if the result is the same as `escaped`.
+fn normalize<M: internal::Matcher>(
+    escaped: &[u8],
+    characters: &[u8],
+    references: &[&[u8]],
+) -> Option<Vec<u8>> {
+    // Avoid allocating if `escaped` is already canonical. Everything before
+    // `start` is, so only the rest needs to be normalized.
+    let start = first_change(escaped, characters, references)?;
+
+    let mut output = Vec::with_capacity(escaped.len());
+    output.extend_from_slice(&escaped[..start]);
+    let push = |output: &mut Vec<u8>, c: u8| match characters
+        .iter()
+        .position(|&e| e == c)
+    {
+        Some(i) => output.extend_from_slice(references[i]),
+        None => output.push(c),
+    };
+
+    let mut rest = &escaped[start..];
+    while let Some((&c, tail)) = rest.split_first() {
+        let entity = if c == b'&' {
+            internal::match_entity_len::<M>(rest)
+        } else {
+            None
+        };
+        if let Some((len, expansion)) = entity {
+            for &c in expansion.iter() {
+                push(&mut output, c);
+            }
+            rest = &rest[len..];
+        } else {
+            if c == b'\r' {
+                // Only a `\r` from a reference needs to be escaped.
+                output.push(c);
+            } else {
+                push(&mut output, c);
+            }
+            rest = tail;
+        }
+    }
+
+    Some(output)
+}
+
+/// Find the first byte in `escaped` that normalizing would change.
+///
+/// That is the first of `characters` that isn’t the start of one of
+/// `references`, which are the canonical spellings of `characters`. A raw
+/// `\r` is left as it is, so it is never a change. Returns `None` if
+/// `escaped` is already canonical.
+fn first_change(
+    escaped: &[u8],
+    characters: &[u8],
+    references: &[&[u8]],
+) -> Option<usize> {
+    let mut i = 0;
+    while let Some(offset) = escaped[i..]
+        .iter()
+        .position(|&c| c != b'\r' && characters.contains(&c))
+    {
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "i + offset < escaped.len()"
+        )]
+        let start = i + offset;
+        match references
+            .iter()
+            .find(|reference| escaped[start..].starts_with(reference))
+        {
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "the reference is in escaped[start..]"
+            )]
+            Some(reference) => i = start + reference.len(),
+            None => return Some(start),
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unescape_in;
+    use assert2::assert;
+
+    /// Normalize `input` in general context with named references.
+    fn named(input: &str) -> Cow<'_, str> {
+        normalize_escaped(input, Context::General, Style::Named)
+    }
+
+    #[test]
+    fn equivalent_spellings() {
+        for input in ["&#x26;", "&#X26;", "&AMP;", "&#38;", "&#0038;", "&amp"] {
+            assert!(named(input) == "&amp;", "input: {input:?}");
+        }
+        assert!(named("&eacute;&Eacute&#xe9;") == "éÉé");
+    }
+
+    #[test]
+    fn bare_ampersand() {
+        assert!(named("a & b") == "a &amp; b");
+        assert!(named("&bogus; &#;") == "&amp;bogus; &amp;#;");
+    }
+
+    #[test]
+    fn quotes() {
+        let input = "&quot;&#39;\"'";
+        assert!(named(input) == "\"'\"'");
+        assert!(
+            normalize_escaped(input, Context::Attribute, Style::Named)
+                == "&quot;'&quot;'"
+        );
+    }
+
+    #[test]
+    fn attribute_context() {
+        assert!(
+            normalize_escaped("&times=&times;", Context::Attribute, Style::Hex)
+                == "&#x26;times=×"
+        );
+        assert!(named("&times=&times;") == "×=×");
+    }
+
+    #[test]
+    fn styles() {
+        let input = "&<>";
+        let normalize =
+            |style| normalize_escaped(input, Context::General, style);
+        assert!(normalize(Style::Named) == "&amp;&lt;&gt;");
+        assert!(normalize(Style::Decimal) == "&#38;&#60;&#62;");
+        assert!(normalize(Style::Hex) == "&#x26;&#x3C;&#x3E;");
+    }
+
+    #[test]
+    fn idempotent() {
+        let input = include_str!("../../tests/corpus/html-escaped.txt");
+        for context in [Context::General, Context::Attribute] {
+            for style in [Style::Named, Style::Decimal, Style::Hex] {
+                let once = normalize_escaped(input, context, style);
+                let twice = normalize_escaped(once.as_ref(), context, style);
+                assert!(once == twice);
+                assert!(matches!(twice, Cow::Borrowed(_)));
+                assert!(
+                    unescape_in(once.as_ref(), context)
+                        == unescape_in(input, context)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn carriage_return() {
+        assert!(named("&#13;&#xD;&#x0d;") == "&#13;&#13;&#13;");
+        assert!(named("\r&amp\r") == "\r&amp;\r");
+        assert!(matches!(named("a\r\nb &#13;"), Cow::Borrowed(_)));
+        assert!(
+            normalize_escaped("&#13;\r&#xD;", Context::Attribute, Style::Hex)
+                == "&#xD;\r&#xD;"
+        );
+    }
+
+    #[test]
+    fn canonical_prefix() {
+        assert!(named("&amp;&lt;x&AMP;&gt;") == "&amp;&lt;x&amp;&gt;");
+        assert!(named("&lt;&lt;<") == "&lt;&lt;&lt;");
+        assert!(named("&amp;&amp") == "&amp;&amp;");
+    }
+
+    #[test]
+    fn borrowed() {
+        assert!(matches!(named("café &amp; &lt;b&gt;"), Cow::Borrowed(_)));
+        assert!(matches!(named("&#38;"), Cow::Owned(_)));
+        for (style, canonical) in [
+            (Style::Decimal, "&#38;&#60;&#62;&#34;"),
+            (Style::Hex, "&#x26;&#x3C;&#x3E;&#x22;"),
+        ] {
+            assert!(matches!(
+                normalize_escaped(canonical, Context::Attribute, style),
+                Cow::Borrowed(_)
+            ));
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Unescape with options that deviate from the WHATWG spec.
+
+use super::{internal, Context};
+use std::borrow::Cow;
+
+/// What to do with a numeric reference to a code point that can’t appear in
+/// text.
+///
+/// This applies to references to U+0000 (e.g. `&#0;`), to surrogates (e.g.
+/// `&#xD800;`), and to numbers above U+10FFFF (e.g. `&#x110000;`). See
+/// [`UnescapeOptions::invalid_code_points()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum InvalidCodePoint {
+    /// Replace the reference with U+FFFD (“�”) as the spec requires. This is
+    /// the default.
+    Replace,
+    /// Remove the reference.
+    Remove,
+    /// Leave the reference as it is, e.g. `&#0;`.
+    Keep,
+}
+
+/// Options for unescaping text that doesn’t quite follow the spec.
+///
+/// [`unescape_in()`](super::unescape_in) follows the [WHATWG spec][spec]
+/// exactly, which is what a browser does. Content from other sources may
+/// need to be treated more or less leniently.
+///
+/// The defaults are the same as `unescape_in(…, Context::General)`:
+///
+/// ```rust
+/// use htmlize::{unescape, InvalidCodePoint, UnescapeOptions};
+/// # use assert2::assert;
+///
+/// let input = "&amp &NBSP; &#x80; &#0;";
+/// assert!(UnescapeOptions::new().unescape(input) == unescape(input));
+///
+/// let options = UnescapeOptions::new()
+///     .bare_entities(false)
+///     .ignore_case(true)
+///     .windows_1252(false)
+///     .invalid_code_points(InvalidCodePoint::Keep);
+/// assert!(options.unescape(input) == "&amp \u{A0} \u{80} &#0;");
+/// ```
+///
+/// [spec]: https://html.spec.whatwg.org/multipage/parsing.html#character-reference-state
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UnescapeOptions {
+    /// Where the text came from.
+    context: Context,
+
+    /// Whether to expand named references without a semicolon.
+    bare_entities: bool,
+
+    /// Whether to remap references to U+0080 through U+009F.
+    windows_1252: bool,
+
+    /// What to do with references to invalid code points.
+    invalid_code_points: InvalidCodePoint,
+
+    /// Whether to match names ignoring ASCII case.
+    ignore_case: bool,
+}
+
+impl UnescapeOptions {
+    /// The default options, which follow the spec for text outside of an
+    /// attribute.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            context: Context::General,
+            bare_entities: true,
+            windows_1252: true,
+            invalid_code_points: InvalidCodePoint::Replace,
+            ignore_case: false,
+        }
+    }
+
+    /// Set the context the text came from. The default is
+    /// [`Context::General`].
+    ///
+    /// See [`unescape_in()`](super::unescape_in) for the differences.
+    #[must_use]
+    pub const fn context(self, context: Context) -> Self {
+        Self { context, ..self }
+    }
+
+    /// Set whether to expand named references without a semicolon, e.g.
+    /// `&amp`. The default is `true`.
+    ///
+    /// The spec only allows this for a few legacy entities like `&amp` and
+    /// `&copy`, but a lot of older content depends on it. Set this to `false`
+    /// to only expand named references with a semicolon.
+    ///
+    /// ```rust
+    /// use htmlize::UnescapeOptions;
+    /// # use assert2::assert;
+    ///
+    /// let options = UnescapeOptions::new().bare_entities(false);
+    /// assert!(options.unescape("&copy 2024 &amp; &#169") == "&copy 2024 & ©");
+    /// ```
+    ///
+    /// Numeric references without a semicolon are still expanded.
+    #[must_use]
+    pub const fn bare_entities(self, bare_entities: bool) -> Self {
+        Self { bare_entities, ..self }
+    }
+
+    /// Set whether to treat numeric references to U+0080 through U+009F as
+    /// Windows-1252, e.g. `&#x80;` as `€`. The default is `true`.
+    ///
+    /// Those code points are C1 control characters, but the spec remaps most
+    /// of them because they were commonly used for Windows-1252 characters.
+    /// Set this to `false` to expand them to the control characters instead.
+    ///
+    /// ```rust
+    /// use htmlize::UnescapeOptions;
+    /// # use assert2::assert;
+    ///
+    /// assert!(UnescapeOptions::new().unescape("&#x80;") == "€");
+    /// assert!(
+    ///     UnescapeOptions::new().windows_1252(false).unescape("&#x80;")
+    ///         == "\u{80}"
+    /// );
+    /// ```
+    #[must_use]
+    pub const fn windows_1252(self, windows_1252: bool) -> Self {
+        Self { windows_1252, ..self }
+    }
+
+    /// Set what to do with numeric references to NUL, surrogates, and
+    /// numbers above U+10FFFF. The default is [`InvalidCodePoint::Replace`].
+    ///
+    /// ```rust
+    /// use htmlize::{InvalidCodePoint, UnescapeOptions};
+    /// # use assert2::assert;
+    ///
+    /// let input = "a&#0;b&#xD800;c";
+    /// let options = UnescapeOptions::new();
+    /// assert!(options.unescape(input) == "a\u{FFFD}b\u{FFFD}c");
+    ///
+    /// let options = options.invalid_code_points(InvalidCodePoint::Remove);
+    /// assert!(options.unescape(input) == "abc");
+    ///
+    /// let options = options.invalid_code_points(InvalidCodePoint::Keep);
+    /// assert!(options.unescape(input) == "a&#0;b&#xD800;c");
+    /// ```
+    #[must_use]
+    pub const fn invalid_code_points(
+        self,
+        invalid_code_points: InvalidCodePoint,
+    ) -> Self {
+        Self { invalid_code_points, ..self }
+    }
+
+    /// Set whether to match entity names ignoring ASCII case, e.g. `&NBSP;`
+    /// as `&nbsp;`. The default is `false`.
+    ///
+    /// This is useful for hand-written content. Names that match exactly are
+    /// always preferred, so `&Gt;` is still `≫` rather than `>`. Otherwise,
+    /// if more than one entity matches, the one with a lowercase name is used.
+    ///
+    /// ```rust
+    /// use htmlize::UnescapeOptions;
+    /// # use assert2::assert;
+    ///
+    /// let options = UnescapeOptions::new().ignore_case(true);
+    /// assert!(options.unescape("&NBSP;&Eacute;&EACUTE;&Gt;") == "\u{A0}Éé≫");
+    /// ```
+    #[must_use]
+    pub const fn ignore_case(self, ignore_case: bool) -> Self {
+        Self { ignore_case, ..self }
+    }
+
+    /// Expand all valid entities using these options.
+    ///
+    /// To work with bytes (`[u8]`) instead of strings, see
+    /// [`unescape_bytes()`](Self::unescape_bytes).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the unescaped bytes are invalid UTF-8. This should be
+    /// impossible.
+    pub fn unescape<'a, S: Into<Cow<'a, str>>>(
+        self,
+        escaped: S,
+    ) -> Cow<'a, str> {
+        let escaped = escaped.into();
+        match self.unescape_internal(escaped.as_bytes()) {
+            Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+            None => escaped,
+        }
+    }
+
+    /// Expand all valid entities in bytes using these options.
+    ///
+    /// ```rust
+    /// use htmlize::UnescapeOptions;
+    /// # use assert2::assert;
+    ///
+    /// let options = UnescapeOptions::new().ignore_case(true);
+    /// assert!(options.unescape_bytes(&b"&LT;\xFF&GT;"[..]) == &b"<\xFF>"[..]);
+    /// ```
+    ///
+    /// To work with `String` instead of bytes, see
+    /// [`unescape()`](Self::unescape).
+    pub fn unescape_bytes<'a, S: Into<Cow<'a, [u8]>>>(
+        self,
+        escaped: S,
+    ) -> Cow<'a, [u8]> {
+        let escaped = escaped.into();
+        match self.unescape_internal(&escaped) {
+            Some(buffer) => buffer.into(),
+            None => escaped,
+        }
+    }
+
+    /// Code that actually does the unescaping.
+    ///
+    /// Returns `None` if no changes would be made.
+    fn unescape_internal(self, escaped: &[u8]) -> Option<Vec<u8>> {
+        internal::replace_references(escaped, |start| {
+            self.match_reference(&escaped[start..])
+        })
+    }
+
+    /// Match a numeric or named entity at the start of `rest`, which starts
+    /// with `&`.
+    ///
+    /// Returns the length of the entity and its expansion.
+    fn match_reference(
+        self,
+        rest: &[u8],
+    ) -> Option<(usize, Cow<'static, [u8]>)> {
+        if rest.get(1) == Some(&b'#') {
+            let mut iter = rest.iter();
+            let number = internal::parse_numeric_entity(&mut iter)?;
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "iter is a subslice of rest"
+            )]
+            let len = rest.len() - iter.as_slice().len();
+            return self
+                .numeric_expansion(number)
+                .map(|expansion| (len, expansion));
+        }
+
+        let exact = internal::longest_entity(rest);
+        let (len, expansion) = if self.ignore_case {
+            // Prefer an exact match unless ignoring case matches more, e.g.
+            // `&notIn;` should be `&notin;` rather than `&not` followed by
+            // `In;`.
+            match (exact, internal::longest_entity_ignore_case(rest)) {
+                (Some(exact), Some(folded)) if folded.0 > exact.0 => folded,
+                (Some(exact), _) => exact,
+                (None, folded) => folded?,
+            }
+        } else {
+            exact?
+        };
+
+        if rest[..len].last() != Some(&b';') {
+            if !self.bare_entities {
+                return None;
+            }
+
+            // In an attribute, entities ending with an alphanumeric character
+            // or '=' instead of ';' are passed through without expansion.
+            //
+            // https://html.spec.whatwg.org/multipage/parsing.html#named-character-reference-state
+            if self.context == Context::Attribute {
+                if let Some(&next) = rest.get(len) {
+                    if next == b'=' || next.is_ascii_alphanumeric() {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        Some((len, expansion.into()))
+    }
+
+    /// Get the expansion of a numeric reference to `number`, or `None` if it
+    /// should be left as it is.
+    fn numeric_expansion(self, number: u32) -> Option<Cow<'static, [u8]>> {
+        match number {
+            0x00 | 0xD800..=0xDFFF | 0x11_0000.. => {
+                match self.invalid_code_points {
+                    InvalidCodePoint::Replace => {
+                        Some(internal::REPLACEMENT_CHAR_BYTES.into())
+                    }
+                    InvalidCodePoint::Remove => Some(Cow::Borrowed(&[])),
+                    InvalidCodePoint::Keep => None,
+                }
+            }
+            0x80..=0x9F if !self.windows_1252 => char::from_u32(number)
+                .map(|c| c.to_string().into_bytes().into()),
+            _ => Some(internal::correct_numeric_entity(number)),
+        }
+    }
+}
+
+impl Default for UnescapeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{unescape_bytes_in, unescape_in};
+    use assert2::assert;
+
+    /// Check that the default options give the same results as
+    /// `unescape_in()`.
+    fn check_default(input: &str) {
+        for context in [Context::General, Context::Attribute] {
+            let options = UnescapeOptions::new().context(context);
+            assert!(
+                options.unescape(input) == unescape_in(input, context),
+                "{context:?}"
+            );
+            assert!(
+                options.unescape_bytes(input.as_bytes())
+                    == unescape_bytes_in(input.as_bytes(), context),
+                "{context:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_edge_cases() {
+        check_default(
+            "&times &times; &timesX &times= &times# &timesbar &notit; \
+            &amp &ampx &amp= &#x1F600; &#128512 &# &; & &&amp; &#0; \
+            &#xD800; &#x110000; &#x1100000000000; &#x80; &#x81; &#x9F",
+        );
+    }
+
+    #[test]
+    fn default_all() {
+        check_default(include_str!(
+            "../../tests/corpus/all-entities-source.txt"
+        ));
+    }
+
+    #[test]
+    fn default_html() {
+        check_default(include_str!("../../tests/corpus/html-escaped.txt"));
+    }
+
+    #[test]
+    fn default_borrowed() {
+        assert!(matches!(
+            UnescapeOptions::new().unescape("a & b &foo;"),
+            Cow::Borrowed(_)
+        ));
+    }
+
+    #[test]
+    fn no_bare_entities() {
+        let options = UnescapeOptions::new().bare_entities(false);
+        assert!(
+            options.unescape("&amp &amp; &ampx &times;") == "&amp & &ampx ×"
+        );
+        assert!(options.unescape("&notit; &notin;") == "&notit; ∉");
+        assert!(options.unescape("&#38 &#x26") == "& &");
+        assert!(
+            options.context(Context::Attribute).unescape("&amp=&amp;")
+                == "&amp=&"
+        );
+    }
+
+    #[test]
+    fn no_windows_1252() {
+        let options = UnescapeOptions::new().windows_1252(false);
+        assert!(options.unescape("&#x80;&#x81;&#x9F;") == "\u{80}\u{81}\u{9F}");
+        assert!(options.unescape("&#x7F;&#xA0;&#x2022;") == "\u{7F}\u{A0}•");
+        assert!(options.unescape("&#0;") == "\u{FFFD}");
+    }
+
+    #[test]
+    fn invalid_code_points() {
+        let input = "&#0 &#x0; &#xD800; &#57343; &#x110000; &#99999999999;";
+        let options = UnescapeOptions::new();
+        assert!(
+            options.unescape(input)
+                == "\u{FFFD} \u{FFFD} \u{FFFD} \u{FFFD} \u{FFFD} \u{FFFD}"
+        );
+        assert!(
+            options
+                .invalid_code_points(InvalidCodePoint::Remove)
+                .unescape(input)
+                == "     "
+        );
+        assert!(
+            options
+                .invalid_code_points(InvalidCodePoint::Keep)
+                .unescape(input)
+                == input
+        );
+        assert!(
+            options
+                .invalid_code_points(InvalidCodePoint::Keep)
+                .unescape("&#0;&#x41;")
+                == "&#0;A"
+        );
+    }
+
+    #[test]
+    fn ignore_case() {
+        let options = UnescapeOptions::new().ignore_case(true);
+        assert!(
+            options.unescape("&NBSP; &Nbsp; &nBsP;") == "\u{A0} \u{A0} \u{A0}"
+        );
+        assert!(options.unescape("&AMP; &Amp &aMp;") == "& & &");
+        assert!(options.unescape("&Gt; &GT; &gT;") == "≫ > >");
+        assert!(options.unescape("&EACUTE; &Eacute;") == "é É");
+        assert!(options.unescape("&notIn; &NOTIT;") == "∉ ¬IT;");
+        assert!(options.unescape("&notequal;") == "≠");
+        assert!(options.unescape("&NOPE; &#X41;") == "&NOPE; A");
+    }
+
+    #[test]
+    fn ignore_case_attribute() {
+        let options = UnescapeOptions::new()
+            .ignore_case(true)
+            .context(Context::Attribute);
+        assert!(options.unescape("&AMP=&AMPx&AMP;&AMP") == "&AMP=&AMPx&&");
+    }
+
+    #[test]
+    fn ignore_case_long() {
+        let options = UnescapeOptions::new().ignore_case(true);
+        assert!(options.unescape("&COUNTERCLOCKWISECONTOURINTEGRAL;") == "∳");
+        assert!(
+            options.unescape("&COUNTERCLOCKWISECONTOURINTEGRALS;")
+                == "&COUNTERCLOCKWISECONTOURINTEGRALS;"
+        );
+    }
+}
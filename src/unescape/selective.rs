@@ -0,0 +1,347 @@
+//! Unescape only some references.
+
+use super::{internal, Context, DefaultMatcher};
+use std::borrow::Cow;
+
+/// Which references [`unescape_selective()`] expands.
+///
+/// [`Filter::new()`] expands everything. Use the other methods to leave some
+/// references escaped.
+///
+/// ```rust
+/// use htmlize::{unescape_selective, Context, Filter};
+/// # use assert2::assert;
+///
+/// let input = "caf&eacute; &lt;b&gt; &#x263A; &nbsp;";
+///
+/// let filter = Filter::new().markup(false);
+/// assert!(
+///     unescape_selective(input, Context::General, &filter)
+///         == "café &lt;b&gt; ☺ \u{A0}"
+/// );
+///
+/// let filter = Filter::numeric_only();
+/// assert!(
+///     unescape_selective(input, Context::General, &filter)
+///         == "caf&eacute; &lt;b&gt; ☺ &nbsp;"
+/// );
+///
+/// let filter = Filter::new().numeric(false).only_named(&["eacute"]);
+/// assert!(
+///     unescape_selective(input, Context::General, &filter)
+///         == "café &lt;b&gt; &#x263A; &nbsp;"
+/// );
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Filter<'a> {
+    /// Whether to expand references to markup-significant characters.
+    markup: bool,
+
+    /// Whether to expand numeric references.
+    numeric: bool,
+
+    /// Which named references to expand.
+    named: Named<'a>,
+}
+
+/// Which named references a [`Filter`] expands.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Named<'a> {
+    /// Expand all named references.
+    All,
+    /// Only expand named references with these names.
+    Only(&'a [&'a str]),
+    /// Expand all named references except for those with these names.
+    Except(&'a [&'a str]),
+}
+
+impl<'a> Filter<'a> {
+    /// A filter that expands every reference, just like
+    /// [`unescape_in()`](super::unescape_in).
+    #[must_use]
+    pub const fn new() -> Self {
+        Self { markup: true, numeric: true, named: Named::All }
+    }
+
+    /// A filter that only expands numeric references like `&#233;`, except
+    /// for references to markup-significant characters like `&#60;`.
+    ///
+    /// This is the same as `Filter::new().markup(false).named(false)`.
+    #[must_use]
+    pub const fn numeric_only() -> Self {
+        Self::new().markup(false).named(false)
+    }
+
+    /// Set whether to expand references to characters that are significant
+    /// in markup: `&`, `<`, `>`, `"`, and `'`. The default is `true`.
+    ///
+    /// Set this to `false` to make stored HTML more readable without making
+    /// it unsafe. This applies to both named and numeric references, e.g.
+    /// `&lt;`, `&LT;`, and `&#60;` are all left as they are.
+    #[must_use]
+    pub const fn markup(self, markup: bool) -> Self {
+        Self { markup, ..self }
+    }
+
+    /// Set whether to expand numeric references like `&#233;` or `&#xE9;`.
+    /// The default is `true`.
+    #[must_use]
+    pub const fn numeric(self, numeric: bool) -> Self {
+        Self { numeric, ..self }
+    }
+
+    /// Set whether to expand named references like `&eacute;`. The default is
+    /// `true`.
+    ///
+    /// This replaces any list set with [`only_named()`](Self::only_named) or
+    /// [`except_named()`](Self::except_named).
+    #[must_use]
+    pub const fn named(self, named: bool) -> Self {
+        let named = if named { Named::All } else { Named::Only(&[]) };
+        Self { named, ..self }
+    }
+
+    /// Only expand named references with these names.
+    ///
+    /// Names don’t include `&` or `;`, e.g. `"eacute"` for `&eacute;`. A name
+    /// covers the form without a semicolon, too, e.g. `&eacute`.
+    #[must_use]
+    pub const fn only_named(self, names: &'a [&'a str]) -> Self {
+        Self { named: Named::Only(names), ..self }
+    }
+
+    /// Expand all named references except for those with these names.
+    ///
+    /// Names don’t include `&` or `;`, e.g. `"nbsp"` for `&nbsp;`. A name
+    /// covers the form without a semicolon, too, e.g. `&nbsp`.
+    #[must_use]
+    pub const fn except_named(self, names: &'a [&'a str]) -> Self {
+        Self { named: Named::Except(names), ..self }
+    }
+
+    /// Check whether `reference` (e.g. `b"&lt;"`) should be expanded to
+    /// `expansion`.
+    fn allows(&self, reference: &[u8], expansion: &[u8]) -> bool {
+        if !self.markup
+            && expansion
+                .iter()
+                .any(|c| matches!(c, b'&' | b'<' | b'>' | b'"' | b'\''))
+        {
+            return false;
+        }
+
+        let name = match reference {
+            [b'&', b'#', ..] => return self.numeric,
+            [b'&', name @ .., b';'] | [b'&', name @ ..] => name,
+            _ => return false,
+        };
+        let listed = |names: &[&str]| {
+            names.iter().any(|listed| listed.as_bytes() == name)
+        };
+        match self.named {
+            Named::All => true,
+            Named::Only(names) => listed(names),
+            Named::Except(names) => !listed(names),
+        }
+    }
+}
+
+impl Default for Filter<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Expand the references allowed by `filter` in a given context.
+///
+/// This works like [`unescape_in()`](super::unescape_in), except that
+/// references that `filter` doesn’t allow are left as they are. See
+/// [`Filter`] for examples.
+///
+/// To work with bytes (`[u8]`) instead of strings, see
+/// [`unescape_bytes_selective()`].
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+pub fn unescape_selective<'a, S: Into<Cow<'a, str>>>(
+    escaped: S,
+    context: Context,
+    filter: &Filter<'_>,
+) -> Cow<'a, str> {
+    let escaped = escaped.into();
+    match unescape_selective_internal(escaped.as_bytes(), context, filter) {
+        Some(buffer) => String::from_utf8(buffer).unwrap().into(),
+        None => escaped,
+    }
+}
+
+/// Expand the references allowed by `filter` in a given context.
+///
+/// This is the same as [`unescape_selective()`], except that it works on bytes
+/// rather than strings.
+///
+/// ```rust
+/// use htmlize::{unescape_bytes_selective, Context, Filter};
+/// # use assert2::assert;
+///
+/// assert!(
+///     unescape_bytes_selective(
+///         &b"&lt;\xFF&eacute;"[..],
+///         Context::General,
+///         &Filter::new().markup(false),
+///     ) == &b"&lt;\xFF\xC3\xA9"[..]
+/// );
+/// ```
+pub fn unescape_bytes_selective<'a, S: Into<Cow<'a, [u8]>>>(
+    escaped: S,
+    context: Context,
+    filter: &Filter<'_>,
+) -> Cow<'a, [u8]> {
+    let escaped = escaped.into();
+    match unescape_selective_internal(&escaped, context, filter) {
+        Some(buffer) => buffer.into(),
+        None => escaped,
+    }
+}
+
+/// Dispatch to [`unescape_filtered()`] for `context`.
+fn unescape_selective_internal(
+    escaped: &[u8],
+    context: Context,
+    filter: &Filter<'_>,
+) -> Option<Vec<u8>> {
+    match context {
+        Context::General => unescape_filtered::<(
+            DefaultMatcher,
+            internal::ContextGeneral,
+        )>(escaped, filter),
+        Context::Attribute => unescape_filtered::<(
+            DefaultMatcher,
+            internal::ContextAttribute,
+        )>(escaped, filter),
+    }
+}
+
+/// Unescape the references in `escaped` that `filter` allows.
+///
+/// Returns `None` if no changes would be made.
+fn unescape_filtered<M: internal::Matcher>(
+    escaped: &[u8],
+    filter: &Filter<'_>,
+) -> Option<Vec<u8>> {
+    internal::replace_references(escaped, |start| {
+        let rest = &escaped[start..];
+        internal::match_entity_len::<M>(rest)
+            .filter(|(len, expansion)| filter.allows(&rest[..*len], expansion))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unescape_in;
+    use assert2::assert;
+
+    /// Unescape `input` in general context with `filter`.
+    fn selective(input: &str, filter: &Filter<'_>) -> String {
+        unescape_selective(input, Context::General, filter).into_owned()
+    }
+
+    #[test]
+    fn new_expands_everything() {
+        let input = include_str!("../../tests/corpus/html-escaped.txt");
+        for context in [Context::General, Context::Attribute] {
+            assert!(
+                unescape_selective(input, context, &Filter::new())
+                    == unescape_in(input, context)
+            );
+        }
+    }
+
+    #[test]
+    fn keep_markup() {
+        let filter = Filter::new().markup(false);
+        assert!(
+            selective(
+                "&lt;&LT;&#60;&#x3c;&lt&gt;&amp;&quot;&apos;&#39;",
+                &filter
+            ) == "&lt;&LT;&#60;&#x3c;&lt&gt;&amp;&quot;&apos;&#39;"
+        );
+        assert!(
+            selective("&eacute;&#233;&times&nbsp;", &filter) == "éé×\u{A0}"
+        );
+    }
+
+    #[test]
+    fn keep_markup_multiple_characters() {
+        // `&nvlt;` is “<⃒”, which contains `<`.
+        let filter = Filter::new().markup(false);
+        assert!(selective("&nvlt;&nle;", &filter) == "&nvlt;≰");
+    }
+
+    #[test]
+    fn numeric_only() {
+        let filter = Filter::numeric_only();
+        assert!(
+            selective("&#233;&#xE9&eacute;&#60;", &filter) == "éé&eacute;&#60;"
+        );
+        assert!(selective("&#0;", &filter) == "\u{FFFD}");
+    }
+
+    #[test]
+    fn numeric_off() {
+        let filter = Filter::new().numeric(false);
+        assert!(selective("&#233;&eacute;&lt;", &filter) == "&#233;é<");
+    }
+
+    #[test]
+    fn only_named() {
+        let filter = Filter::new().only_named(&["eacute", "amp"]);
+        assert!(
+            selective("&eacute;&eacute &Eacute;&amp;&amp&AMP;&#38;", &filter)
+                == "éé &Eacute;&&&AMP;&"
+        );
+    }
+
+    #[test]
+    fn except_named() {
+        let filter = Filter::new().except_named(&["nbsp", "shy"]);
+        assert!(
+            selective("a&nbsp;b&shy;c&nbsp&eacute;&#xA0;", &filter)
+                == "a&nbsp;b&shy;c&nbspé\u{A0}"
+        );
+    }
+
+    #[test]
+    fn named_off() {
+        let filter = Filter::new().named(false);
+        assert!(selective("&eacute;&#233;", &filter) == "&eacute;é");
+        let filter = filter.except_named(&[]);
+        assert!(selective("&eacute;&#233;", &filter) == "éé");
+    }
+
+    #[test]
+    fn attribute() {
+        let filter = Filter::new().markup(false);
+        assert!(
+            unescape_selective(
+                "&times=&times;&lt=",
+                Context::Attribute,
+                &filter
+            ) == "&times=×&lt="
+        );
+    }
+
+    #[test]
+    fn borrowed() {
+        assert!(matches!(
+            unescape_selective(
+                "&lt; &foo;",
+                Context::General,
+                &Filter::numeric_only()
+            ),
+            Cow::Borrowed(_)
+        ));
+    }
+}
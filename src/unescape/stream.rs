@@ -0,0 +1,280 @@
+//! Incremental unescaping of input that arrives in chunks.
+
+use super::{internal, Context, DefaultMatcher};
+
+/// Unescape input that arrives in chunks, e.g. from a network socket.
+///
+/// Entities may be split across chunks. If a chunk ends with something that
+/// might be the start of an entity, like `&tim` or `&#x1F6`, it is held back
+/// until the next chunk (or [`finish()`](Self::finish)) decides it.
+///
+/// ```rust
+/// use htmlize::{Context, Unescaper};
+/// # use assert2::assert;
+///
+/// let mut unescaper = Unescaper::new(Context::General);
+/// let mut output = Vec::new();
+/// unescaper.feed(b"3 &tim", &mut output);
+/// unescaper.feed(b"es; 4 &#x3", &mut output);
+/// unescaper.feed(b"E; 10", &mut output);
+/// unescaper.finish(&mut output);
+/// assert!(output == "3 × 4 > 10".as_bytes());
+/// ```
+///
+/// The output is the same as calling [`unescape_bytes_in()`] on all of the
+/// input at once.
+///
+/// [`unescape_bytes_in()`]: super::unescape_bytes_in
+#[derive(Clone, Debug)]
+pub struct Unescaper {
+    /// The context used to expand entities.
+    context: Context,
+
+    /// Input that might be the start of an incomplete entity.
+    pending: Vec<u8>,
+}
+
+impl Unescaper {
+    /// Create a new `Unescaper` that expands entities according to the rules
+    /// for `context`. See [`unescape_in()`](super::unescape_in) for details.
+    #[must_use]
+    pub const fn new(context: Context) -> Self {
+        Self { context, pending: Vec::new() }
+    }
+
+    /// Unescape the next chunk of input and append it to `output`.
+    ///
+    /// A possibly incomplete entity at the end of `chunk` is held back rather
+    /// than appended to `output`.
+    pub fn feed(&mut self, chunk: &[u8], output: &mut Vec<u8>) {
+        match self.context {
+            Context::General => feed::<(
+                DefaultMatcher,
+                internal::ContextGeneral,
+            )>(&mut self.pending, chunk, output),
+            Context::Attribute => {
+                feed::<(DefaultMatcher, internal::ContextAttribute)>(
+                    &mut self.pending,
+                    chunk,
+                    output,
+                );
+            }
+        }
+    }
+
+    /// Unescape anything that was held back and append it to `output`.
+    ///
+    /// This treats the end of the last chunk as the end of the input.
+    pub fn finish(self, output: &mut Vec<u8>) {
+        match self.context {
+            Context::General => internal::unescape_into::<(
+                DefaultMatcher,
+                internal::ContextGeneral,
+            )>(&self.pending, output),
+            Context::Attribute => internal::unescape_into::<(
+                DefaultMatcher,
+                internal::ContextAttribute,
+            )>(&self.pending, output),
+        }
+    }
+}
+
+/// Unescape as much of `pending` followed by `chunk` as possible.
+///
+/// Anything that can’t be unescaped without more input is left in `pending`.
+fn feed<M: internal::Matcher>(
+    pending: &mut Vec<u8>,
+    chunk: &[u8],
+    output: &mut Vec<u8>,
+) {
+    if pending.is_empty() {
+        // Avoid copying the chunk if possible.
+        let end = incomplete_start::<M>(chunk);
+        internal::unescape_into::<M>(&chunk[..end], output);
+        pending.extend_from_slice(&chunk[end..]);
+    } else {
+        pending.extend_from_slice(chunk);
+        let end = incomplete_start::<M>(pending);
+        internal::unescape_into::<M>(&pending[..end], output);
+        pending.drain(..end);
+    }
+
+    compact_numeric(pending);
+}
+
+/// Find the start of a possibly incomplete entity at the end of `escaped`.
+///
+/// Returns `escaped.len()` if there isn’t one. Since an entity can’t contain
+/// ‘&’, everything before the returned index can be unescaped as if it was the
+/// end of the input.
+fn incomplete_start<M: internal::Matcher>(escaped: &[u8]) -> usize {
+    let i = match memchr::memrchr(b'&', escaped) {
+        Some(i) => i,
+        None => return escaped.len(),
+    };
+
+    let incomplete = match &escaped[i..] {
+        [b'&', b'#', b'x' | b'X', digits @ ..] => {
+            digits.iter().all(u8::is_ascii_hexdigit)
+        }
+        [b'&', b'#', digits @ ..] => digits.iter().all(u8::is_ascii_digit),
+        // A named entity can’t be longer than `M::ENTITY_MAX_LENGTH`, so if we
+        // already have that many bytes the following input can’t matter.
+        tail @ [b'&', name @ ..] => {
+            tail.len() < M::ENTITY_MAX_LENGTH
+                && name.iter().all(u8::is_ascii_alphanumeric)
+        }
+        _ => unreachable!("memrchr() found '&'"),
+    };
+
+    if incomplete {
+        i
+    } else {
+        escaped.len()
+    }
+}
+
+/// Shorten a pending numeric entity without changing its expansion.
+///
+/// Numeric entities may have any number of digits, so this keeps a stream of
+/// digits from filling up memory. It drops redundant leading zeros and replaces
+/// any number that is too large to be a code point with a shorter one.
+fn compact_numeric(pending: &mut Vec<u8>) {
+    // No code point has more than 7 decimal or 6 hexadecimal digits. 99999999
+    // is too large in either base, so it will still expand to U+FFFD.
+    const TOO_LARGE: &[u8] = b"99999999";
+
+    let prefix_len = match pending.as_slice() {
+        [b'&', b'#', b'x' | b'X', ..] => 3,
+        [b'&', b'#', ..] => 2,
+        _ => return,
+    };
+
+    // Keep at least one digit so that the entity is still expanded.
+    let digits = &pending[prefix_len..];
+    let zeros = digits
+        .iter()
+        .take_while(|&&c| c == b'0')
+        .count()
+        .min(digits.len().saturating_sub(1));
+    #[allow(clippy::arithmetic_side_effects, reason = "zeros < digits.len()")]
+    pending.drain(prefix_len..prefix_len + zeros);
+
+    if pending.len().saturating_sub(prefix_len) > TOO_LARGE.len() {
+        pending.truncate(prefix_len);
+        pending.extend_from_slice(TOO_LARGE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unescape_bytes_in;
+    use assert2::assert;
+
+    /// Feed `input` to an `Unescaper` in chunks of `size` bytes.
+    fn unescape_chunked(
+        input: &[u8],
+        context: Context,
+        size: usize,
+    ) -> Vec<u8> {
+        let mut unescaper = Unescaper::new(context);
+        let mut output = Vec::new();
+        for chunk in input.chunks(size) {
+            unescaper.feed(chunk, &mut output);
+        }
+        unescaper.finish(&mut output);
+        output
+    }
+
+    /// Check that every chunk size produces the same result as unescaping the
+    /// input all at once.
+    fn check_all_chunk_sizes(input: &str) {
+        for context in [Context::General, Context::Attribute] {
+            let expected = unescape_bytes_in(input.as_bytes(), context);
+            for size in 1..=input.len().max(1) {
+                assert!(
+                    unescape_chunked(input.as_bytes(), context, size)
+                        == *expected,
+                    "chunk size {size} in {context:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn empty() {
+        check_all_chunk_sizes("");
+    }
+
+    #[test]
+    fn split_named() {
+        check_all_chunk_sizes("a &times; b &times c &timesbar; d &timesbar");
+    }
+
+    #[test]
+    fn split_attribute_rules() {
+        check_all_chunk_sizes("&timesX &times= &times# &amp=");
+    }
+
+    #[test]
+    fn split_numeric() {
+        check_all_chunk_sizes("&#x1F600; &#128512; &#x7A &#122z &#; &#x; &#");
+    }
+
+    #[test]
+    fn split_ampersands() {
+        check_all_chunk_sizes("&&amp;&&#&#x&");
+    }
+
+    #[test]
+    fn split_long_invalid() {
+        check_all_chunk_sizes(
+            "&aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa;",
+        );
+    }
+
+    #[test]
+    fn all_entities() {
+        const ALL_SOURCE: &str =
+            include_str!("../../tests/corpus/all-entities-source.txt");
+        const ALL_EXPANDED: &str =
+            include_str!("../../tests/corpus/all-entities-expanded.txt");
+        for size in [1, 2, 3, 5, 7, 64] {
+            assert!(
+                unescape_chunked(ALL_SOURCE.as_bytes(), Context::General, size)
+                    == ALL_EXPANDED.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn long_numeric_is_compacted() {
+        let mut unescaper = Unescaper::new(Context::General);
+        let mut output = Vec::new();
+        unescaper.feed(b"&#x", &mut output);
+        for _ in 0..1000 {
+            unescaper.feed(b"0000000000", &mut output);
+        }
+        unescaper.feed(b"7A", &mut output);
+        assert!(unescaper.pending.len() < 16);
+        for _ in 0..1000 {
+            unescaper.feed(b"1111111111", &mut output);
+        }
+        assert!(unescaper.pending.len() < 16);
+        unescaper.feed(b";", &mut output);
+        unescaper.finish(&mut output);
+        assert!(output == "\u{fffd}".as_bytes());
+    }
+
+    #[test]
+    fn leading_zeros_are_compacted() {
+        let mut unescaper = Unescaper::new(Context::General);
+        let mut output = Vec::new();
+        unescaper.feed(b"&#0", &mut output);
+        unescaper.feed(b"000", &mut output);
+        unescaper.feed(b"0122", &mut output);
+        unescaper.finish(&mut output);
+        assert!(output == b"z");
+    }
+}
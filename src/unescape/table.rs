@@ -0,0 +1,691 @@
+//! A set of named entities that can be changed at runtime.
+
+use super::dtd::{parse_dtd, DtdError, DtdLimits};
+use super::{unescape_bytes_with, unescape_with, Context, EntityMatcher};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::{fmt, fs, io, path::Path};
+
+/// A set of named entities that can be changed at runtime.
+///
+/// This is useful for content that uses nonstandard entities, e.g. `&logo;`.
+/// [`EntityTable::new()`] starts with the same entities as [`ENTITIES`]. Names
+/// are written just like they are in [`ENTITIES`], so `"&logo;"` and `"&logo"`
+/// are separate entities. The second one can be used without a semicolon.
+///
+/// Names that end with `;` may also contain `.`, `-`, `_`, and `:`, as in XML,
+/// e.g. `&b.alpha;`.
+///
+/// Unescaping with a table follows the same rules as
+/// [`unescape_in()`](super::unescape_in), including the rules for entities
+/// without semicolons in [`Context::Attribute`].
+///
+/// ```rust
+/// use htmlize::{Context, EntityTable};
+/// # use assert2::assert;
+///
+/// let mut table = EntityTable::new();
+/// table.insert("&trade2;", "™²").unwrap();
+/// table.insert("&logo", "<img src=logo.png>").unwrap();
+/// table.remove("&trade;");
+///
+/// assert!(table.unescape_in("&trade2; &logo &trade;", Context::General)
+///     == "™² <img src=logo.png> &trade;");
+/// assert!(table.unescape_in("&logo=&lt;", Context::Attribute) == "&logo=<");
+/// ```
+///
+/// Entities can also be loaded from JSON in the same format as the [official
+/// list] with [`load_json()`](Self::load_json) or
+/// [`load_json_file()`](Self::load_json_file).
+///
+/// [`ENTITIES`]: crate::ENTITIES
+/// [official list]: https://html.spec.whatwg.org/entities.json
+#[derive(Clone, Debug)]
+pub struct EntityTable {
+    /// Map from entity name, e.g. `&amp;`, to its expansion.
+    entities: BTreeMap<String, String>,
+
+    /// The length of the longest name that has been inserted, including the
+    /// `&` and `;`. This is not reduced when entities are removed.
+    max_len: usize,
+}
+
+impl EntityTable {
+    /// Create a table with all of the entities defined by the [WHATWG HTML
+    /// spec][spec], i.e. everything in [`ENTITIES`](crate::ENTITIES).
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entity in [`ENTITIES`](crate::ENTITIES) is invalid UTF-8.
+    /// This should be impossible.
+    ///
+    /// [spec]: https://html.spec.whatwg.org/multipage/named-characters.html#named-character-references
+    #[must_use]
+    pub fn new() -> Self {
+        let mut table = Self::empty();
+        for (name, expansion) in &crate::ENTITIES {
+            // All names and expansions are valid UTF-8.
+            table.insert_unchecked(
+                std::str::from_utf8(name).unwrap().to_owned(),
+                std::str::from_utf8(expansion).unwrap().to_owned(),
+            );
+        }
+        table
+    }
+
+    /// Create a table with no entities.
+    ///
+    /// Numeric entities like `&#38;` are still expanded when unescaping.
+    #[must_use]
+    pub fn empty() -> Self {
+        Self { entities: BTreeMap::new(), max_len: 0 }
+    }
+
+    /// Add an entity, or replace it if it already exists.
+    ///
+    /// `name` must be `&` followed by one or more ASCII letters or digits,
+    /// optionally ending with `;`, e.g. `&trade2;`. Names ending with `;` may
+    /// also contain `.`, `-`, `_`, and `:`. Returns the expansion the entity
+    /// had before, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EntityTableError::InvalidName`] if `name` is not a valid
+    /// entity name.
+    pub fn insert<N, E>(
+        &mut self,
+        name: N,
+        expansion: E,
+    ) -> Result<Option<String>, EntityTableError>
+    where
+        N: Into<String>,
+        E: Into<String>,
+    {
+        let name = name.into();
+        check_name(&name)?;
+        Ok(self.insert_unchecked(name, expansion.into()))
+    }
+
+    /// Add an entity without checking its name.
+    pub(super) fn insert_unchecked(
+        &mut self,
+        name: String,
+        expansion: String,
+    ) -> Option<String> {
+        self.max_len = self.max_len.max(name.len());
+        self.entities.insert(name, expansion)
+    }
+
+    /// Remove an entity, e.g. `"&amp;"`, and return its expansion.
+    ///
+    /// This only removes the exact name passed, so removing `"&amp;"` does
+    /// not remove `"&amp"`.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.entities.remove(name)
+    }
+
+    /// Get the expansion of an entity, e.g. `"&amp;"`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entities.get(name).map(String::as_str)
+    }
+
+    /// The number of entities in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// Whether the table has no entities.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Add entities from JSON in the same format as the [official list].
+    ///
+    /// ```rust
+    /// use htmlize::{Context, EntityTable};
+    /// # use assert2::assert;
+    ///
+    /// let mut table = EntityTable::empty();
+    /// table
+    ///     .load_json(r#"{
+    ///         "&logo;": { "characters": "★" },
+    ///         "&star2;": { "codepoints": [9733, 9733] }
+    ///     }"#)
+    ///     .unwrap();
+    /// assert!(table.unescape_in("&logo;&star2;&amp;", Context::General) == "★★★&amp;");
+    /// ```
+    ///
+    /// Each entity needs either `"characters"` or `"codepoints"`. If it has
+    /// both, `"characters"` is used. Entities that are already in the table
+    /// are replaced.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EntityTableError::Json`] if the JSON is invalid,
+    /// [`EntityTableError::Format`] if it’s not in the expected format, or
+    /// [`EntityTableError::InvalidName`] if it contains an invalid entity
+    /// name. Nothing is added if there is an
+    /// error.
+    ///
+    /// [official list]: https://html.spec.whatwg.org/entities.json
+    pub fn load_json(&mut self, json: &str) -> Result<(), EntityTableError> {
+        for (name, expansion) in parse_json(json)? {
+            self.insert_unchecked(name, expansion);
+        }
+        Ok(())
+    }
+
+    /// Add entities from a JSON file in the same format as the [official
+    /// list].
+    ///
+    /// See [`load_json()`](Self::load_json) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EntityTableError::Io`] if the file could not be read, or any
+    /// of the errors returned by [`load_json()`](Self::load_json).
+    ///
+    /// [official list]: https://html.spec.whatwg.org/entities.json
+    pub fn load_json_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> Result<(), EntityTableError> {
+        let json =
+            fs::read_to_string(path).map_err(EntityTableError::Io)?;
+        self.load_json(&json)
+    }
+
+    /// Add the general entities declared in a DTD with the default
+    /// [`DtdLimits`].
+    ///
+    /// This understands `<!ENTITY>` declarations in XML DTDs and SGML entity
+    /// sets, e.g. the ISO sets (`isonum`, `isolat1`, etc.) used by TEI and
+    /// [DocBook]. Each entity `name` is added as `&name;`.
+    ///
+    /// ```rust
+    /// use htmlize::{Context, EntityTable};
+    /// # use assert2::assert;
+    ///
+    /// let mut table = EntityTable::empty();
+    /// table
+    ///     .load_dtd(r#"
+    ///         <!ENTITY % product "Widget">
+    ///         <!ENTITY half "&#x000BD;" ><!--=fraction one-half -->
+    ///         <!ENTITY name "%product; Pro&trade;">
+    ///         <!ENTITY trade "&#x2122;">
+    ///     "#)
+    ///     .unwrap();
+    /// assert!(table.unescape_in("&name; &half;", Context::General) == "Widget Pro™ ½");
+    /// ```
+    ///
+    /// Like an XML parser, this:
+    ///
+    ///   * Expands character references like `&#38;` and parameter entity
+    ///     references like `%name;` in entity values when they’re declared.
+    ///   * Expands general entity references like `&name;` in entity values
+    ///     when they’re added to the table. They may refer to entities
+    ///     declared later, entities already in the table, or the predefined
+    ///     XML entities, e.g. `&amp;`.
+    ///   * Uses the first declaration if an entity is declared more than once.
+    ///   * Parses the text of parameter entities referenced between
+    ///     declarations, e.g. `%ISOnum;`.
+    ///   * Handles `INCLUDE` and `IGNORE` conditional sections.
+    ///   * Skips comments, processing instructions, and other declarations.
+    ///
+    /// SGML `CDATA` entities are added as is. External entities (`SYSTEM` or
+    /// `PUBLIC`) can’t be loaded and are skipped, as are SGML `SDATA` entities
+    /// and other special entity types.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DtdError`] if the DTD can’t be parsed, an entity can’t be
+    /// expanded, or a [limit](DtdLimits) is exceeded. Nothing is added if
+    /// there is an error.
+    ///
+    /// [DocBook]: https://docbook.org/
+    pub fn load_dtd(&mut self, dtd: &str) -> Result<(), DtdError> {
+        self.load_dtd_with_limits(dtd, DtdLimits::new())
+    }
+
+    /// Add the general entities declared in a DTD with custom limits.
+    ///
+    /// See [`load_dtd()`](Self::load_dtd) for details.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DtdError`] if the DTD can’t be parsed, an entity can’t be
+    /// expanded, or a limit is exceeded. Nothing is added if there is an
+    /// error.
+    pub fn load_dtd_with_limits(
+        &mut self,
+        dtd: &str,
+        limits: DtdLimits,
+    ) -> Result<(), DtdError> {
+        for (name, expansion) in parse_dtd(self, dtd, limits)? {
+            self.insert_unchecked(format!("&{name};"), expansion);
+        }
+        Ok(())
+    }
+
+    /// Expand all valid entities in a given context using this table.
+    ///
+    /// This is the same as [`unescape_with()`] with this table as the
+    /// matcher. See [`unescape_in()`](super::unescape_in) for an explanation
+    /// of `context`.
+    ///
+    /// To work with bytes (`[u8]`) instead of strings, see
+    /// [`unescape_bytes_in()`](Self::unescape_bytes_in).
+    pub fn unescape_in<'a, S: Into<Cow<'a, str>>>(
+        &self,
+        escaped: S,
+        context: Context,
+    ) -> Cow<'a, str> {
+        unescape_with(self, escaped, context)
+    }
+
+    /// Expand all valid entities in a given context using this table.
+    ///
+    /// This is the same as [`unescape_in()`](Self::unescape_in), except that
+    /// it works on bytes rather than strings.
+    pub fn unescape_bytes_in<'a, S: Into<Cow<'a, [u8]>>>(
+        &self,
+        escaped: S,
+        context: Context,
+    ) -> Cow<'a, [u8]> {
+        unescape_bytes_with(self, escaped, context)
+    }
+
+    /// Look up an entity by name as bytes.
+    fn lookup(&self, name: &[u8]) -> Option<&str> {
+        std::str::from_utf8(name).ok().and_then(|name| self.get(name))
+    }
+}
+
+impl Default for EntityTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EntityMatcher for EntityTable {
+    fn match_entity(&self, input: &[u8]) -> Option<(usize, Cow<'_, str>)> {
+        // Every name is `&`, name characters, and maybe `;`. Names without
+        // `;` only contain letters and digits.
+        let input = &input[..input.len().min(self.max_len)];
+        let name_len =
+            input.iter().skip(1).take_while(|&&c| is_name_byte(c)).count();
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "name_len < input.len()"
+        )]
+        let end = name_len + 1;
+
+        if input.get(end) == Some(&b';') {
+            if let Some(expansion) = self.lookup(&input[..=end]) {
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "end < input.len()"
+                )]
+                let len = end + 1;
+                return Some((len, expansion.into()));
+            }
+        }
+
+        let letters = input
+            .get(1..end)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .count();
+        #[allow(
+            clippy::arithmetic_side_effects,
+            reason = "letters < input.len()"
+        )]
+        let end = letters + 1;
+        (2..=end).rev().find_map(|len| {
+            self.lookup(&input[..len]).map(|expansion| (len, expansion.into()))
+        })
+    }
+}
+
+/// An error returned when building an [`EntityTable`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EntityTableError {
+    /// An entity name was not `&` followed by one or more ASCII letters or
+    /// digits, optionally ending with `;`. See [`EntityTable::insert()`].
+    InvalidName(String),
+    /// JSON could not be parsed.
+    Json {
+        /// What was wrong with the JSON.
+        message: String,
+        /// The line where the error was found, starting at 1.
+        line: usize,
+        /// The column where the error was found, starting at 1.
+        column: usize,
+    },
+    /// JSON was valid, but not in the expected format.
+    Format(String),
+    /// A JSON file could not be read.
+    Io(io::Error),
+}
+
+impl fmt::Display for EntityTableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => {
+                write!(f, "invalid entity name {name:?}")
+            }
+            Self::Json { message, line, column } => write!(
+                f,
+                "invalid entity JSON at line {line} column {column}: {message}"
+            ),
+            Self::Format(message) => {
+                write!(f, "unexpected entity JSON: {message}")
+            }
+            Self::Io(error) => {
+                write!(f, "could not read entity JSON: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EntityTableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::InvalidName(_) | Self::Json { .. } | Self::Format(_) => None,
+        }
+    }
+}
+
+/// Check that `name` is `&` followed by ASCII letters or digits, and maybe
+/// `;`. Names ending with `;` may also contain other [name bytes].
+///
+/// [name bytes]: is_name_byte
+pub(super) fn check_name(name: &str) -> Result<(), EntityTableError> {
+    let valid = name.strip_prefix('&').map_or(false, |body| {
+        match body.strip_suffix(';') {
+            Some(body) => !body.is_empty() && body.bytes().all(is_name_byte),
+            None => {
+                !body.is_empty()
+                    && body.bytes().all(|c| c.is_ascii_alphanumeric())
+            }
+        }
+    });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(EntityTableError::InvalidName(name.to_owned()))
+    }
+}
+
+/// Check if `c` can be part of an entity name that ends with `;`.
+pub(super) const fn is_name_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'.' | b'-' | b'_' | b':')
+}
+
+/// Parse JSON in the format of the official `entities.json`.
+///
+/// Returns a list of entity names and expansions.
+fn parse_json(json: &str) -> Result<Vec<(String, String)>, EntityTableError> {
+    let entities: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(json).map_err(|error| json_error(&error))?;
+
+    entities
+        .into_iter()
+        .map(|(name, entity)| {
+            check_name(&name)?;
+            let expansion = expansion(&entity).map_err(|message| {
+                EntityTableError::Format(format!("entity {name:?} {message}"))
+            })?;
+            Ok((name, expansion))
+        })
+        .collect()
+}
+
+/// Convert a [`serde_json::Error`] into [`EntityTableError::Json`].
+///
+/// This keeps `serde_json` types out of the public API.
+fn json_error(error: &serde_json::Error) -> EntityTableError {
+    // The message from serde_json ends with the location, which is stored
+    // separately.
+    let (line, column) = (error.line(), error.column());
+    let mut message = error.to_string();
+    let location = format!(" at line {line} column {column}");
+    if message.ends_with(&location) {
+        message.truncate(message.len().saturating_sub(location.len()));
+    }
+    EntityTableError::Json { message, line, column }
+}
+
+/// Get the expansion of an entity from its JSON value, or a description of
+/// what’s wrong with it.
+fn expansion(entity: &serde_json::Value) -> Result<String, &'static str> {
+    use serde_json::Value;
+
+    match (entity.get("characters"), entity.get("codepoints")) {
+        (Some(Value::String(characters)), _) => Ok(characters.clone()),
+        (Some(_), _) => Err("has \"characters\" that isn’t a string"),
+        (None, Some(Value::Array(codepoints))) => codepoints
+            .iter()
+            .map(|code| {
+                code.as_u64()
+                    .and_then(|code| u32::try_from(code).ok())
+                    .and_then(char::from_u32)
+                    .ok_or("has an invalid code point")
+            })
+            .collect(),
+        (None, Some(_)) => Err("has \"codepoints\" that isn’t an array"),
+        (None, None) => Err("has neither \"characters\" nor \"codepoints\""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::unescape_in;
+    use assert2::{assert, let_assert};
+
+    #[test]
+    fn new_matches_unescape_in() {
+        let table = EntityTable::new();
+        for input in [
+            include_str!("../../tests/corpus/all-entities-source.txt"),
+            include_str!("../../tests/corpus/html-escaped.txt"),
+            "&times &times; &timesX &times= &timesbar &notit; &amp &# &; &",
+        ] {
+            for context in [Context::General, Context::Attribute] {
+                assert!(
+                    table.unescape_in(input, context)
+                        == unescape_in(input, context),
+                    "{context:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn load_official_json() {
+        let mut table = EntityTable::empty();
+        table.load_json(include_str!("../../entities.json")).unwrap();
+        assert!(table.entities == EntityTable::new().entities);
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut table = EntityTable::new();
+        assert!(table.insert("&trade2;", "™2").unwrap() == None);
+        assert!(table.insert("&amp;", "and").unwrap().as_deref() == Some("&"));
+        assert!(table.remove("&lt").as_deref() == Some("<"));
+        assert!(table.remove("&nope;") == None);
+        assert!(
+            table.unescape_in("&trade2;&amp;&amp&lt;&lt &gt", Context::General)
+                == "™2and&<&lt >"
+        );
+    }
+
+    #[test]
+    fn bare_custom_entity() {
+        let mut table = EntityTable::empty();
+        table.insert("&logo", "L").unwrap();
+        assert!(table.unescape_in("&logo;&logox", Context::General) == "L;Lx");
+        assert!(
+            table.unescape_in("&logo;&logox &logo", Context::Attribute)
+                == "L;&logox L"
+        );
+    }
+
+    #[test]
+    fn longest_match_after_insert() {
+        let mut table = EntityTable::empty();
+        table.insert("&a", "1").unwrap();
+        table.insert("&abcdefgh;", "2").unwrap();
+        assert!(table.unescape_in("&abcdefgh; &abc", Context::General) == "2 1bc");
+    }
+
+    #[test]
+    fn xml_name_characters() {
+        let mut table = EntityTable::empty();
+        table.insert("&b.alpha;", "𝛂").unwrap();
+        table.insert("&b", "B").unwrap();
+        table.insert("&x-y_z:1;", "X").unwrap();
+        assert!(
+            table.unescape_in("&b.alpha; &b.beta; &x-y_z:1;", Context::General)
+                == "𝛂 B.beta; X"
+        );
+        assert!(
+            table.unescape_in("&b.alpha; &b.beta;", Context::Attribute)
+                == "𝛂 B.beta;"
+        );
+    }
+
+    #[test]
+    fn empty_table() {
+        let table = EntityTable::empty();
+        assert!(table.is_empty());
+        assert!(
+            table.unescape_in("&amp; &#38;", Context::General) == "&amp; &"
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        let mut table = EntityTable::empty();
+        for name in ["", "&", "&;", "amp;", "&a-b", "&a;;", "&é;", "&a b"] {
+            assert!(
+                let Err(EntityTableError::InvalidName(_)) =
+                    table.insert(name, "x")
+            );
+        }
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn json_escapes() {
+        let mut table = EntityTable::empty();
+        table
+            .load_json(
+                r#" { "&a;" : { "characters" : "\"\\\/\b\f\n\r\té😀",
+                "extra": [1, {"x": null}, true, false, -1.5e3] },
+                "&b": {"codepoints": [], "characters": ""},
+                "&c": {"codepoints": [128512, 10]} } "#,
+            )
+            .unwrap();
+        assert!(table.get("&a;") == Some("\"\\/\u{8}\u{c}\n\r\té😀"));
+        assert!(table.get("&b") == Some(""));
+        assert!(table.get("&c") == Some("😀\n"));
+    }
+
+    #[test]
+    fn json_errors() {
+        for json in [
+            "",
+            "{",
+            "[]",
+            r#"{"&a": {"characters": "\x"}}"#,
+            r#"{"&a": {"characters": "\uD800"}}"#,
+            "{} x",
+        ] {
+            let mut table = EntityTable::empty();
+            assert!(
+                let Err(EntityTableError::Json { .. }) = table.load_json(json),
+                "{json}"
+            );
+            assert!(table.is_empty());
+        }
+
+        let mut table = EntityTable::empty();
+        let_assert!(
+            Err(EntityTableError::Json { message, line, column }) =
+                table.load_json("{\n  x")
+        );
+        assert!(message == "key must be a string");
+        assert!((line, column) == (2, 3));
+    }
+
+    #[test]
+    fn json_format_errors() {
+        for json in [
+            r#"{"&a": {}}"#,
+            r#"{"&a": {"characters": 1}}"#,
+            r#"{"&a": {"codepoints": 1}}"#,
+            r#"{"&a": {"codepoints": [1.5]}}"#,
+            r#"{"&a": {"codepoints": [55296]}}"#,
+            r#"{"&a": {"codepoints": [4294967296]}}"#,
+        ] {
+            let mut table = EntityTable::empty();
+            assert!(
+                let Err(EntityTableError::Format(_)) = table.load_json(json),
+                "{json}"
+            );
+            assert!(table.is_empty());
+        }
+
+        let mut table = EntityTable::empty();
+        let_assert!(
+            Err(error) = table.load_json(r#"{"&a": {"codepoints": [55296]}}"#)
+        );
+        assert!(
+            error.to_string()
+                == r#"unexpected entity JSON: entity "&a" has an invalid code point"#
+        );
+    }
+
+    #[test]
+    fn json_invalid_name() {
+        let mut table = EntityTable::empty();
+        let_assert!(
+            Err(EntityTableError::InvalidName(name)) =
+                table.load_json(r#"{"&b;": {"characters": ""}, "c;": {}}"#)
+        );
+        assert!(name == "c;");
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn json_deeply_nested() {
+        let json = format!(
+            r#"{{"&a": {{"characters": "", "x": {}{}}}}}"#,
+            "[".repeat(1000),
+            "]".repeat(1000)
+        );
+        let mut table = EntityTable::empty();
+        assert!(
+            let Err(EntityTableError::Json { .. }) = table.load_json(&json)
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        let mut table = EntityTable::empty();
+        assert!(
+            let Err(EntityTableError::Io(_)) =
+                table.load_json_file("does/not/exist.json")
+        );
+    }
+}
@@ -0,0 +1,423 @@
+//! # Escape and unescape XML 1.0
+//!
+//! XML only has five predefined entities, and every reference must end with a
+//! semicolon, so the HTML functions are not correct for XML.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
+
+/// Escape a string used in XML character data, i.e. text between tags.
+///
+/// This escapes `&`, `<`, and `>`, and escapes `\r` as `&#13;` so that it
+/// isn’t turned into `\n` when the XML is parsed. Characters that are not
+/// allowed in XML 1.0, e.g. most C0 control characters, are replaced with
+/// U+FFFD REPLACEMENT CHARACTER.
+///
+/// **Do not use this in attributes.**
+///
+/// ```rust
+/// use htmlize::escape_xml_text;
+/// # use assert2::assert;
+///
+/// assert!(escape_xml_text("a < b & \"c\"\u{0}") == "a &lt; b &amp; \"c\"\u{fffd}");
+/// ```
+pub fn escape_xml_text<'a, S: Into<Cow<'a, str>>>(input: S) -> Cow<'a, str> {
+    escape_xml(input.into(), |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '\r' => Some("&#13;"),
+        _ => None,
+    })
+}
+
+/// Escape a string to be used in a quoted XML attribute.
+///
+/// This escapes `&`, `<`, `>`, `"`, and `'`, so it works with either kind of
+/// quotes. It also escapes `\t`, `\n`, and `\r` as `&#9;`, `&#10;`, and
+/// `&#13;`, since a parser would otherwise [normalize them][normalize] to
+/// spaces. Characters that are not allowed in XML 1.0 are replaced with U+FFFD
+/// REPLACEMENT CHARACTER.
+///
+/// ```rust
+/// use htmlize::escape_xml_attribute;
+/// # use assert2::assert;
+///
+/// assert!(
+///     escape_xml_attribute("a\tb\n'c' & \"d\"")
+///         == "a&#9;b&#10;&apos;c&apos; &amp; &quot;d&quot;"
+/// );
+/// ```
+///
+/// [normalize]: https://www.w3.org/TR/xml/#AVNormalize
+pub fn escape_xml_attribute<'a, S: Into<Cow<'a, str>>>(
+    input: S,
+) -> Cow<'a, str> {
+    escape_xml(input.into(), |c| match c {
+        '&' => Some("&amp;"),
+        '<' => Some("&lt;"),
+        '>' => Some("&gt;"),
+        '"' => Some("&quot;"),
+        '\'' => Some("&apos;"),
+        '\t' => Some("&#9;"),
+        '\n' => Some("&#10;"),
+        '\r' => Some("&#13;"),
+        _ => None,
+    })
+}
+
+/// Escape characters with `map()`, and replace characters that are not
+/// allowed in XML with U+FFFD.
+fn escape_xml(
+    input: Cow<'_, str>,
+    map: fn(char) -> Option<&'static str>,
+) -> Cow<'_, str> {
+    let needs_escape = |c: char| map(c).is_some() || !is_xml_char(c.into());
+
+    // Avoid allocating if nothing needs to be escaped.
+    let start = match input.find(needs_escape) {
+        Some(start) => start,
+        None => return input,
+    };
+
+    let mut output = String::with_capacity(input.len().saturating_mul(2));
+    output.push_str(&input[..start]);
+    for c in input[start..].chars() {
+        if let Some(entity) = map(c) {
+            output.push_str(entity);
+        } else if is_xml_char(c.into()) {
+            output.push(c);
+        } else {
+            output.push('\u{FFFD}');
+        }
+    }
+
+    output.into()
+}
+
+/// Check if a code point is allowed in an XML 1.0 document.
+///
+/// See the [`Char` production](https://www.w3.org/TR/xml/#NT-Char).
+const fn is_xml_char(c: u32) -> bool {
+    matches!(
+        c,
+        0x9 | 0xA | 0xD | 0x20..=0xD7FF | 0xE000..=0xFFFD | 0x1_0000..=0x10_FFFF
+    )
+}
+
+/// A kind of error found by [`unescape_xml()`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum XmlErrorKind {
+    /// `&` was not followed by a name or number and `;`, e.g. `&amp` or
+    /// `& `.
+    MalformedReference,
+    /// A reference to an entity other than the five predefined ones, e.g.
+    /// `&copy;`.
+    UnknownEntity,
+    /// A numeric reference to a character that is not allowed in XML 1.0,
+    /// e.g. `&#0;` or `&#xD800;`.
+    InvalidCharacter,
+}
+
+impl fmt::Display for XmlErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::MalformedReference => "malformed reference",
+            Self::UnknownEntity => "unknown entity",
+            Self::InvalidCharacter => "invalid character",
+        })
+    }
+}
+
+/// An error returned by [`unescape_xml()`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct XmlUnescapeError {
+    /// What’s wrong with the reference.
+    pub kind: XmlErrorKind,
+    /// The byte range of the reference in the input.
+    pub range: Range<usize>,
+    /// The text of the reference, e.g. `"&copy;"`.
+    pub text: String,
+}
+
+impl fmt::Display for XmlUnescapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid XML reference {:?} at bytes {}..{}: {}",
+            self.text, self.range.start, self.range.end, self.kind
+        )
+    }
+}
+
+impl std::error::Error for XmlUnescapeError {}
+
+/// Expand references in XML 1.0 text or attribute values.
+///
+/// This only expands the five predefined entities (`&amp;`, `&lt;`, `&gt;`,
+/// `&quot;`, and `&apos;`) and numeric references like `&#38;` and `&#x26;`.
+/// Unlike HTML, XML requires the semicolon and treats anything else as an
+/// error.
+///
+/// ```rust
+/// use htmlize::unescape_xml;
+/// # use assert2::assert;
+///
+/// assert!(unescape_xml("&lt;a&gt; &amp;&#38;&#x26;").unwrap() == "<a> &&&");
+/// ```
+///
+/// This does not do anything else a parser would, e.g. it doesn’t normalize
+/// line breaks or whitespace in attributes.
+///
+/// # Errors
+///
+/// Returns [`XmlUnescapeError`] for the first reference that isn’t valid XML:
+///
+/// ```rust
+/// use htmlize::{unescape_xml, XmlErrorKind};
+/// # use assert2::assert;
+///
+/// let error = unescape_xml("&copy; 2024").unwrap_err();
+/// assert!(error.kind == XmlErrorKind::UnknownEntity);
+/// assert!(error.range == (0..6));
+///
+/// let error = unescape_xml("AT&T").unwrap_err();
+/// assert!(error.kind == XmlErrorKind::MalformedReference);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the unescaped bytes are invalid UTF-8. This should be impossible.
+pub fn unescape_xml<'a, S: Into<Cow<'a, str>>>(
+    escaped: S,
+) -> Result<Cow<'a, str>, XmlUnescapeError> {
+    let escaped = escaped.into();
+    let raw = escaped.as_bytes();
+
+    let mut start = match memchr::memchr(b'&', raw) {
+        Some(start) => start,
+        None => return Ok(escaped),
+    };
+
+    let mut buffer = Vec::with_capacity(raw.len());
+    buffer.extend_from_slice(&raw[..start]);
+    loop {
+        let (expansion, len) =
+            match_reference(&raw[start..]).map_err(|(kind, len)| {
+                #[allow(
+                    clippy::arithmetic_side_effects,
+                    reason = "len is within raw"
+                )]
+                let range = start..start + len;
+                XmlUnescapeError {
+                    kind,
+                    text: String::from_utf8_lossy(&raw[range.clone()])
+                        .into_owned(),
+                    range,
+                }
+            })?;
+
+        let mut utf8 = [0; 4];
+        buffer.extend_from_slice(expansion.encode_utf8(&mut utf8).as_bytes());
+
+        #[allow(clippy::arithmetic_side_effects, reason = "len is within raw")]
+        let end = start + len;
+        let rest = &raw[end..];
+        if let Some(i) = memchr::memchr(b'&', rest) {
+            buffer.extend_from_slice(&rest[..i]);
+            #[allow(
+                clippy::arithmetic_side_effects,
+                reason = "i is within raw"
+            )]
+            let next = end + i;
+            start = next;
+        } else {
+            buffer.extend_from_slice(rest);
+            break;
+        }
+    }
+
+    Ok(String::from_utf8(buffer).unwrap().into())
+}
+
+/// Match a reference at the start of `raw`, which starts with `&`.
+///
+/// Returns the expansion and the length of the reference, or the error and
+/// the length of the invalid reference.
+fn match_reference(raw: &[u8]) -> Result<(char, usize), (XmlErrorKind, usize)> {
+    let (digits, radix) = match raw {
+        [b'&', b'#', b'x', rest @ ..] => (rest, 16),
+        [b'&', b'#', rest @ ..] => (rest, 10),
+        [b'&', rest @ ..] => return match_named(rest),
+        _ => unreachable!("match_reference() called without '&'"),
+    };
+    #[allow(clippy::arithmetic_side_effects, reason = "raw starts with prefix")]
+    let prefix_len = raw.len() - digits.len();
+
+    let count = digits
+        .iter()
+        .take_while(|c| char::from(**c).is_digit(radix))
+        .count();
+    #[allow(clippy::arithmetic_side_effects, reason = "count <= digits.len()")]
+    let len = prefix_len + count;
+    if count == 0 || digits.get(count) != Some(&b';') {
+        return Err((XmlErrorKind::MalformedReference, len));
+    }
+    #[allow(clippy::arithmetic_side_effects, reason = "the ';' is in raw")]
+    let len = len + 1;
+
+    // Digits are ASCII, so this can’t fail. Numbers that are too large to be
+    // a code point fail to parse.
+    let number = std::str::from_utf8(&digits[..count]).unwrap();
+    u32::from_str_radix(number, radix)
+        .ok()
+        .filter(|&c| is_xml_char(c))
+        .and_then(char::from_u32)
+        .map(|c| (c, len))
+        .ok_or((XmlErrorKind::InvalidCharacter, len))
+}
+
+/// Match a named reference after the `&`.
+///
+/// Returns the expansion and the length of the reference including `&`, or
+/// the error and the length of the invalid reference.
+fn match_named(rest: &[u8]) -> Result<(char, usize), (XmlErrorKind, usize)> {
+    let name_len = rest
+        .iter()
+        .take_while(|&&c| {
+            c.is_ascii_alphanumeric() || matches!(c, b'_' | b':' | b'-' | b'.')
+        })
+        .count();
+    #[allow(clippy::arithmetic_side_effects, reason = "name is within rest")]
+    let len = name_len + 1;
+    if name_len == 0 || rest.get(name_len) != Some(&b';') {
+        return Err((XmlErrorKind::MalformedReference, len));
+    }
+    #[allow(clippy::arithmetic_side_effects, reason = "the ';' is in rest")]
+    let len = len + 1;
+
+    match &rest[..name_len] {
+        b"amp" => Ok(('&', len)),
+        b"lt" => Ok(('<', len)),
+        b"gt" => Ok(('>', len)),
+        b"quot" => Ok(('"', len)),
+        b"apos" => Ok(('\'', len)),
+        _ => Err((XmlErrorKind::UnknownEntity, len)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert2::assert;
+
+    macro_rules! test {
+        ($name:ident, $($test:tt)+) => {
+            #[test]
+            fn $name() {
+                assert!($($test)+);
+            }
+        };
+    }
+
+    test!(
+        escape_text_clean,
+        escape_xml_text("a \"b\" 'c'") == "a \"b\" 'c'"
+    );
+    test!(
+        escape_text_special,
+        escape_xml_text("&<>\t\n\r]]>") == "&amp;&lt;&gt;\t\n&#13;]]&gt;"
+    );
+    test!(
+        escape_attribute_special,
+        escape_xml_attribute("&<>\"'\t\n\r")
+            == "&amp;&lt;&gt;&quot;&apos;&#9;&#10;&#13;"
+    );
+    test!(
+        escape_illegal,
+        escape_xml_text("\u{0}\u{8}\u{B}\u{1F}\u{FFFE}\u{FFFF}\u{10000}")
+            == "\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}\u{10000}"
+    );
+
+    #[test]
+    fn escape_clean_is_borrowed() {
+        assert!(matches!(escape_xml_text("café"), Cow::Borrowed(_)));
+        assert!(matches!(escape_xml_attribute("café"), Cow::Borrowed(_)));
+    }
+
+    test!(unescape_empty, unescape_xml("").unwrap() == "");
+    test!(
+        unescape_predefined,
+        unescape_xml("&amp;&lt;&gt;&quot;&apos;").unwrap() == "&<>\"'"
+    );
+    test!(
+        unescape_numeric,
+        unescape_xml("&#65;&#x42;&#x1F600;&#0000067;").unwrap() == "AB😀C"
+    );
+    test!(
+        unescape_surrounding_text,
+        unescape_xml("é &amp; é").unwrap() == "é & é"
+    );
+
+    #[test]
+    fn unescape_clean_is_borrowed() {
+        assert!(matches!(unescape_xml("a < b"), Ok(Cow::Borrowed(_))));
+    }
+
+    /// Check that unescaping `input` fails with `kind` for `text`.
+    fn check_error(input: &str, kind: XmlErrorKind, text: &str) {
+        let error = unescape_xml(input).unwrap_err();
+        assert!(error.kind == kind, "{input:?}");
+        assert!(error.text == text, "{input:?}");
+        assert!(input[error.range] == *text, "{input:?}");
+    }
+
+    #[test]
+    fn unescape_malformed() {
+        use XmlErrorKind::MalformedReference;
+        check_error("a & b", MalformedReference, "&");
+        check_error("&", MalformedReference, "&");
+        check_error("&amp", MalformedReference, "&amp");
+        check_error("&amp b", MalformedReference, "&amp");
+        check_error("&;", MalformedReference, "&");
+        check_error("&#;", MalformedReference, "&#");
+        check_error("&#x;", MalformedReference, "&#x");
+        check_error("&#X41;", MalformedReference, "&#");
+        check_error("&#65", MalformedReference, "&#65");
+        check_error("&#x4G;", MalformedReference, "&#x4");
+    }
+
+    #[test]
+    fn unescape_unknown() {
+        check_error("&copy;", XmlErrorKind::UnknownEntity, "&copy;");
+        check_error("&AMP;", XmlErrorKind::UnknownEntity, "&AMP;");
+        check_error("&amp;&my-ent;", XmlErrorKind::UnknownEntity, "&my-ent;");
+    }
+
+    #[test]
+    fn unescape_invalid_character() {
+        use XmlErrorKind::InvalidCharacter;
+        check_error("&#0;", InvalidCharacter, "&#0;");
+        check_error("&#x8;", InvalidCharacter, "&#x8;");
+        check_error("&#xD800;", InvalidCharacter, "&#xD800;");
+        check_error("&#xFFFE;", InvalidCharacter, "&#xFFFE;");
+        check_error("&#x110000;", InvalidCharacter, "&#x110000;");
+        check_error(
+            "&#99999999999999999999;",
+            InvalidCharacter,
+            "&#99999999999999999999;",
+        );
+    }
+
+    #[test]
+    fn error_display() {
+        let error = unescape_xml("a &nbsp;").unwrap_err();
+        assert!(
+            error.to_string()
+                == "invalid XML reference \"&nbsp;\" at bytes 2..8: unknown \
+                entity"
+        );
+    }
+}